@@ -0,0 +1,683 @@
+//! The grammar of the `#inline_c_rs` directives, shared by the
+//! `libafl_inline_c` crate, which applies them, and its macros, which
+//! report mistakes at compile time.
+//!
+//! A directive stands on its own line:
+//!
+//! ```text
+//! directive := "#inline_c_rs" name [":" value]
+//! value     := string | integer | boolean | list | name ["=" rest-of-line]
+//! string    := '"' (character | escape)* '"'
+//! escape    := '\' ('\' | '"' | "'" | 'n' | 'r' | 't' | '0' | 'x' hex hex | "u{" hex+ "}")
+//! list      := "[" [string ("," string)* [","]] "]"
+//! boolean   := "true" | "false"
+//! ```
+//!
+//! Strings and lists can span several lines. Which value a directive
+//! takes is given by its [`Kind`].
+
+use std::fmt;
+
+/// What a directive takes as value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// No value, or a boolean, e.g. `#inline_c_rs SHARED`.
+    Flag,
+    /// A string, or an integer, e.g. `#inline_c_rs CC: "clang"`.
+    String,
+    /// A string, or a list of strings, e.g. `#inline_c_rs INCLUDE: ["a"]`.
+    Strings,
+    /// A name with an optional value, the rest of the line, e.g.
+    /// `#inline_c_rs DEFINE: NAME=VALUE`.
+    KeyValue,
+    /// A string argument, and a string, e.g.
+    /// `#inline_c_rs FIXTURE "input.txt": "contents"`.
+    Named,
+}
+
+impl Kind {
+    fn example(self, name: &str) -> String {
+        match self {
+            Self::Flag => format!("`#inline_c_rs {}`", name),
+            Self::String => format!("`#inline_c_rs {}: \"value\"`", name),
+            Self::Strings => format!("`#inline_c_rs {}: [\"a\", \"b\"]`", name),
+            Self::KeyValue => format!("`#inline_c_rs {}: NAME=VALUE`", name),
+            Self::Named => format!("`#inline_c_rs {} \"name\": \"value\"`", name),
+        }
+    }
+}
+
+/// The known directives.
+pub const DIRECTIVES: &[(&str, Kind)] = &[
+    ("SHARED", Kind::Flag),
+    ("HERMETIC", Kind::Flag),
+    ("DEBUG", Kind::Flag),
+    ("DIFFERENTIAL", Kind::Flag),
+    ("ENV_CLEAR", Kind::Flag),
+    ("SOURCE_COVERAGE", Kind::Flag),
+    ("FORKSERVER", Kind::Flag),
+    ("TARGET", Kind::String),
+    ("CC", Kind::String),
+    ("CXX", Kind::String),
+    ("CC_MIN", Kind::String),
+    ("CFLAGS", Kind::String),
+    ("CPPFLAGS", Kind::String),
+    ("CXXFLAGS", Kind::String),
+    ("LDFLAGS", Kind::String),
+    ("OPT_LEVEL", Kind::String),
+    ("STD", Kind::String),
+    ("DIFFERENTIAL_COMPILERS", Kind::Strings),
+    ("INCLUDE", Kind::Strings),
+    ("LINK", Kind::Strings),
+    ("LINK_SEARCH", Kind::Strings),
+    ("OBJECTS", Kind::Strings),
+    ("PKG_CONFIG", Kind::Strings),
+    ("ENV_REMOVE", Kind::Strings),
+    ("DEFINE", Kind::KeyValue),
+    ("ENV", Kind::KeyValue),
+    ("RLIMIT", Kind::KeyValue),
+    ("FIXTURE", Kind::Named),
+];
+
+/// Return the kind of the directive `name`, if it is known.
+pub fn kind(name: &str) -> Option<Kind> {
+    DIRECTIVES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, kind)| *kind)
+}
+
+/// The value of a directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    String(String),
+    List(Vec<String>),
+    /// The name, and the value as written, e.g. `"Hello"` with its
+    /// quotes. See [`unquote`].
+    KeyValue(String, Option<String>),
+    /// The argument, and the value.
+    Named(String, String),
+}
+
+/// A directive of a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Directive {
+    pub name: String,
+    pub value: Value,
+    /// The line of the program where the directive starts, from 1.
+    pub line: usize,
+}
+
+/// A directive that does not follow the grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    /// The line of the program where the mistake is, from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid directive on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The keyword starting a directive.
+pub const KEYWORD: &str = "#inline_c_rs";
+
+/// Parse the directives of a program. The program is returned without
+/// them; each directive is replaced by as many empty lines as it spans,
+/// so that the lines of the compiler diagnostics are unchanged.
+pub fn parse(program: &str) -> Result<(String, Vec<Directive>), Error> {
+    let mut output = String::with_capacity(program.len());
+    let mut directives = Vec::new();
+    let mut parser = Parser {
+        text: program,
+        position: 0,
+        line: 1,
+    };
+
+    while parser.position < program.len() {
+        let line_start = parser.position;
+        let line_end = program[line_start..]
+            .find('\n')
+            .map_or(program.len(), |end| line_start + end + 1);
+        let line = &program[line_start..line_end];
+        let indentation = line.len() - line.trim_start().len();
+
+        match line.trim_start().strip_prefix(KEYWORD) {
+            Some(after) if after.is_empty() || after.starts_with(char::is_whitespace) => {
+                let first_line = parser.line;
+                parser.position = line_start + indentation + KEYWORD.len();
+                directives.push(parser.directive()?);
+
+                // The new line ending the directive is kept.
+                output.extend((first_line..parser.line).map(|_| '\n'));
+            }
+            _ => {
+                output.push_str(line);
+                parser.position = line_end;
+                parser.line += 1;
+            }
+        }
+    }
+
+    Ok((output, directives))
+}
+
+/// Return the value of a string literal, e.g. `Hello` for `"Hello"`, or
+/// `None` if `text` is not a single string literal.
+pub fn unquote(text: &str) -> Option<String> {
+    let mut parser = Parser {
+        text,
+        position: 0,
+        line: 1,
+    };
+    let string = parser.string().ok()?;
+
+    if parser.position == text.len() {
+        Some(string)
+    } else {
+        None
+    }
+}
+
+/// Return the known directive whose name is the closest to `name`, if
+/// any is close enough, e.g. `SHARED` for `SHARD`.
+pub fn suggestion(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_uppercase();
+
+    DIRECTIVES
+        .iter()
+        .map(|(known, _)| (distance(&name, known), *known))
+        .filter(|(distance, known)| *distance <= (known.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between two strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+struct Parser<'p> {
+    text: &'p str,
+    position: usize,
+    line: usize,
+}
+
+impl<'p> Parser<'p> {
+    fn error<T>(&self, message: String) -> Result<T, Error> {
+        Err(Error {
+            line: self.line,
+            message,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.position += character.len_utf8();
+
+        if character == '\n' {
+            self.line += 1;
+        }
+
+        Some(character)
+    }
+
+    /// Skip the whitespace of the current line.
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\r')) {
+            self.bump();
+        }
+    }
+
+    /// Skip the whitespace, including new lines.
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn at_end_of_line(&self) -> bool {
+        matches!(self.peek(), None | Some('\n'))
+    }
+
+    /// Consume the end of the line, which must be blank.
+    fn end_of_line(&mut self, after: &str) -> Result<(), Error> {
+        self.skip_blanks();
+
+        if !self.at_end_of_line() {
+            let unexpected = self.rest_of_line().trim_end();
+
+            return self.error(format!("unexpected `{}` after {}", unexpected, after));
+        }
+
+        self.bump();
+
+        Ok(())
+    }
+
+    fn rest_of_line(&mut self) -> &'p str {
+        let start = self.position;
+
+        while !self.at_end_of_line() {
+            self.bump();
+        }
+
+        &self.text[start..self.position]
+    }
+
+    fn name(&mut self) -> Option<&'p str> {
+        let start = self.position;
+
+        if !self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        {
+            return None;
+        }
+
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.bump();
+        }
+
+        Some(&self.text[start..self.position])
+    }
+
+    fn directive(&mut self) -> Result<Directive, Error> {
+        let line = self.line;
+
+        self.skip_blanks();
+
+        let name = match self.name() {
+            Some(name) => name,
+            None => {
+                return self.error(format!(
+                    "expected the name of a directive after `{}`",
+                    KEYWORD
+                ))
+            }
+        };
+
+        let kind = match kind(name) {
+            Some(kind) => kind,
+            None => {
+                return self.error(match suggestion(name) {
+                    Some(suggestion) => format!(
+                        "unknown directive `{}`, did you mean `{}`?",
+                        name, suggestion
+                    ),
+                    None => format!(
+                        "unknown directive `{}`; use `#inline_c_rs ENV: {}=value` to set an environment variable of the program",
+                        name, name
+                    ),
+                })
+            }
+        };
+
+        self.skip_blanks();
+
+        let argument = match kind {
+            Kind::Named if self.peek() == Some('"') => {
+                let argument = self.string()?;
+
+                self.skip_blanks();
+
+                Some(argument)
+            }
+            Kind::Named => {
+                return self.error(format!(
+                    "`{}` expects a string argument, e.g. {}",
+                    name,
+                    kind.example(name)
+                ))
+            }
+            _ => None,
+        };
+
+        let value = if self.at_end_of_line() {
+            match kind {
+                Kind::Flag => Value::Bool(true),
+                _ => {
+                    return self.error(format!(
+                        "`{}` expects a value, e.g. {}",
+                        name,
+                        kind.example(name)
+                    ))
+                }
+            }
+        } else if self.peek() == Some(':') {
+            self.bump();
+            self.skip_blanks();
+            self.value(name, kind)?
+        } else {
+            return self.error(format!(
+                "expected `:` or the end of the line after `{}`",
+                name
+            ));
+        };
+
+        self.end_of_line(&format!("the value of `{}`", name))?;
+
+        let value = match (argument, value) {
+            (Some(argument), Value::String(value)) => Value::Named(argument, value),
+            (_, value) => value,
+        };
+
+        Ok(Directive {
+            name: name.to_string(),
+            value,
+            line,
+        })
+    }
+
+    fn value(&mut self, name: &str, kind: Kind) -> Result<Value, Error> {
+        let expected = |parser: &Self| {
+            parser.error(format!(
+                "invalid value for `{}`, expected e.g. {}",
+                name,
+                kind.example(name)
+            ))
+        };
+
+        match (kind, self.peek()) {
+            (Kind::Flag, _) => match self.name() {
+                Some("true") => Ok(Value::Bool(true)),
+                Some("false") => Ok(Value::Bool(false)),
+                _ => self.error(format!(
+                    "`{}` takes no value, or `true` or `false`, e.g. {}",
+                    name,
+                    kind.example(name)
+                )),
+            },
+            (Kind::String, Some('"')) | (Kind::Strings, Some('"')) | (Kind::Named, Some('"')) => {
+                let string = self.string()?;
+
+                Ok(match kind {
+                    Kind::String | Kind::Named => Value::String(string),
+                    _ => Value::List(vec![string]),
+                })
+            }
+            (Kind::String, Some('0'..='9')) => {
+                let start = self.position;
+
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.bump();
+                }
+
+                Ok(Value::String(self.text[start..self.position].to_string()))
+            }
+            (Kind::Strings, Some('[')) => self.list().map(Value::List),
+            (Kind::KeyValue, _) => {
+                let key = match self.name() {
+                    Some(key) => key.to_string(),
+                    None => return expected(self),
+                };
+
+                self.skip_blanks();
+
+                if self.peek() != Some('=') {
+                    return Ok(Value::KeyValue(key, None));
+                }
+
+                self.bump();
+                self.skip_blanks();
+
+                // A string can span several lines, and be followed by
+                // more text, e.g. `"a" "b"` for a definition.
+                let start = self.position;
+
+                if self.peek() == Some('"') {
+                    self.string()?;
+                }
+
+                self.rest_of_line();
+
+                Ok(Value::KeyValue(
+                    key,
+                    Some(self.text[start..self.position].trim_end().to_string()),
+                ))
+            }
+            _ => expected(self),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<String>, Error> {
+        let mut list = Vec::new();
+
+        // `[`
+        self.bump();
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(']') => break,
+                Some('"') => list.push(self.string()?),
+                _ => return self.error(String::from("expected a string or `]` in the list")),
+            }
+
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => break,
+                _ => return self.error(String::from("expected `,` or `]` after a list item")),
+            }
+        }
+
+        // `]`
+        self.bump();
+
+        Ok(list)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let mut string = String::new();
+
+        if self.bump() != Some('"') {
+            return self.error(String::from("expected a string"));
+        }
+
+        loop {
+            match self.bump() {
+                None => return self.error(String::from("unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('\'') => '\'',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some('x') => self.hexadecimal_escape()?,
+                        Some('u') => self.unicode_escape()?,
+                        Some(other) => {
+                            return self.error(format!("unknown escape `\\{}` in a string", other))
+                        }
+                        None => return self.error(String::from("unterminated string")),
+                    };
+
+                    string.push(escaped);
+                }
+                Some(character) => string.push(character),
+            }
+        }
+    }
+
+    /// `\x7f`, an ASCII character.
+    fn hexadecimal_escape(&mut self) -> Result<char, Error> {
+        let digits = self.text.get(self.position..self.position + 2);
+
+        match digits.and_then(|digits| u8::from_str_radix(digits, 16).ok()) {
+            Some(byte) if byte.is_ascii() => {
+                self.position += 2;
+
+                Ok(char::from(byte))
+            }
+            _ => self.error(String::from(
+                "invalid `\\x` escape, expected two hexadecimal digits up to `7f`",
+            )),
+        }
+    }
+
+    /// `\u{1f600}`, a Unicode character.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let rest = &self.text[self.position..];
+        let character = rest
+            .strip_prefix('{')
+            .and_then(|rest| rest.split_once('}'))
+            .and_then(|(digits, _)| {
+                let character = char::from_u32(u32::from_str_radix(digits, 16).ok()?)?;
+
+                Some((digits.len(), character))
+            });
+
+        match character {
+            Some((length, character)) => {
+                self.position += length + 2;
+
+                Ok(character)
+            }
+            None => self.error(String::from(
+                "invalid `\\u` escape, expected e.g. `\\u{1f600}`",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (program, directives) = parse(
+            "#inline_c_rs SHARED\n\
+             \x20   #inline_c_rs DEBUG: false\n\
+             #inline_c_rs CC: \"clang \\\"quoted\\\"\\n\\x41\\u{e9}\"\n\
+             #inline_c_rs OPT_LEVEL: 2\n\
+             #inline_c_rs CFLAGS: \"\"\n\
+             #inline_c_rs INCLUDE: [\n    \"a\",\n    \"b c\",\n]\n\
+             #inline_c_rs DEFINE: GREETING = \"Hello, \" \"World!\"\n\
+             #inline_c_rs DEFINE: FLAG\n\
+             #inline_c_rs FIXTURE \"input.txt\": \"a\\nb\"\n\
+             int main() {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(program, "\n\n\n\n\n\n\n\n\n\n\n\nint main() {}\n");
+        assert_eq!(
+            directives
+                .into_iter()
+                .map(|directive| (directive.name, directive.value, directive.line))
+                .collect::<Vec<_>>(),
+            vec![
+                (String::from("SHARED"), Value::Bool(true), 1),
+                (String::from("DEBUG"), Value::Bool(false), 2),
+                (
+                    String::from("CC"),
+                    Value::String(String::from("clang \"quoted\"\nAé")),
+                    3
+                ),
+                (
+                    String::from("OPT_LEVEL"),
+                    Value::String(String::from("2")),
+                    4
+                ),
+                (String::from("CFLAGS"), Value::String(String::new()), 5),
+                (
+                    String::from("INCLUDE"),
+                    Value::List(vec![String::from("a"), String::from("b c")]),
+                    6
+                ),
+                (
+                    String::from("DEFINE"),
+                    Value::KeyValue(
+                        String::from("GREETING"),
+                        Some(String::from("\"Hello, \" \"World!\""))
+                    ),
+                    10
+                ),
+                (
+                    String::from("DEFINE"),
+                    Value::KeyValue(String::from("FLAG"), None),
+                    11
+                ),
+                (
+                    String::from("FIXTURE"),
+                    Value::Named(String::from("input.txt"), String::from("a\nb")),
+                    12
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message = |program: &str| parse(program).unwrap_err().to_string();
+
+        assert_eq!(
+            message("int x;\n#inline_c_rs SHARD\n"),
+            "Invalid directive on line 2: unknown directive `SHARD`, did you mean `SHARED`?"
+        );
+        assert_eq!(
+            message("#inline_c_rs shared"),
+            "Invalid directive on line 1: unknown directive `shared`, did you mean `SHARED`?"
+        );
+        assert_eq!(
+            message("#inline_c_rs FOO: \"bar\""),
+            "Invalid directive on line 1: unknown directive `FOO`; use `#inline_c_rs ENV: FOO=value` to set an environment variable of the program"
+        );
+        assert!(message("#inline_c_rs CC").contains("`CC` expects a value"));
+        assert!(message("#inline_c_rs CC: clang").contains("invalid value for `CC`"));
+        assert!(message("#inline_c_rs SHARED: \"yes\"").contains("takes no value"));
+        assert!(message("#inline_c_rs CC: \"clang").contains("unterminated string"));
+        assert!(message("#inline_c_rs CC: \"\\q\"").contains("unknown escape `\\q`"));
+        assert!(message("#inline_c_rs CC: \"a\" \"b\"").contains("unexpected `\"b\"`"));
+        assert!(message("#inline_c_rs INCLUDE: [\"a\" \"b\"]").contains("expected `,` or `]`"));
+        assert!(message("#inline_c_rs FIXTURE: \"a\"").contains("expects a string argument"));
+        assert!(message("#inline_c_rs FIXTURE \"a\"").contains("`FIXTURE` expects a value"));
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote("\"a \\\"b\\\"\""), Some(String::from("a \"b\"")));
+        assert_eq!(unquote("\"a\" \"b\""), None);
+        assert_eq!(unquote("a"), None);
+    }
+}
@@ -1,35 +1,404 @@
 //! Please see the `inline-c` crate to learn more.
-#![feature(proc_macro_span)]
-use proc_macro2::TokenStream;
-use quote::quote;
+use proc_macro2::{Ident, LineColumn, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned};
+
+mod matrix;
 
 /// Execute a C program and return a `Result` of
 /// `inline_c::Assert`. See examples inside the `inline-c` crate.
+///
+/// The program is either written with Rust tokens, or given as a single
+/// string literal (`assert_c!(r#"…"#)`) which is compiled verbatim.
 #[proc_macro]
 pub fn assert_c(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = TokenStream::from(input);
-    let input_as_string = reconstruct(input);
-
-    quote!(
-        libafl_inline_c::run(libafl_inline_c::Language::C, #input_as_string).map_err(|e| panic!("{}", e)).unwrap()
+    expand(
+        quote!(libafl_inline_c::Language::C),
+        TokenStream::from(input),
     )
     .into()
 }
 
 /// Execute a C++ program and return a `Result` of
 /// `inline_c::Assert`. See examples inside the `inline-c` crate.
+///
+/// The program is either written with Rust tokens, or given as a single
+/// string literal (`assert_cxx!(r#"…"#)`) which is compiled verbatim.
 #[proc_macro]
 pub fn assert_cxx(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = TokenStream::from(input);
-    let input_as_string = reconstruct(input);
+    expand(
+        quote!(libafl_inline_c::Language::Cxx),
+        TokenStream::from(input),
+    )
+    .into()
+}
 
-    quote!(
-        libafl_inline_c::run(libafl_inline_c::Language::Cxx, #input_as_string).map_err(|e| panic!("{}", e)).unwrap()
+/// Execute the C program in the file at the given path, relative to
+/// `CARGO_MANIFEST_DIR`, and return a `Result` of `inline_c::Assert`.
+///
+/// The file is tracked like with `include_str!`, so editing it rebuilds
+/// the test. Includes are resolved relative to the directory of the file.
+#[proc_macro]
+pub fn assert_c_file(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_file(
+        quote!(libafl_inline_c::Language::C),
+        TokenStream::from(input),
     )
     .into()
 }
 
-fn reconstruct(input: TokenStream) -> String {
+/// Execute the C++ program in the file at the given path, relative to
+/// `CARGO_MANIFEST_DIR`, and return a `Result` of `inline_c::Assert`.
+///
+/// The file is tracked like with `include_str!`, so editing it rebuilds
+/// the test. Includes are resolved relative to the directory of the file.
+#[proc_macro]
+pub fn assert_cxx_file(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_file(
+        quote!(libafl_inline_c::Language::Cxx),
+        TokenStream::from(input),
+    )
+    .into()
+}
+
+/// Expand a test function into one `#[test]` per combination of target
+/// triple, compiler and optimization level. The `assert_c!` and
+/// `assert_cxx!` of the test body are compiled with the configuration of
+/// each combination:
+///
+/// ```rust,ignore
+/// #[libafl_inline_c::test(
+///     targets = ["host", "x86_64-pc-windows-gnu"],
+///     compilers = ["gcc", "clang"],
+///     opt_levels = [0, 2],
+/// )]
+/// fn test_harness() {
+///     (assert_c! {
+///         int main() {
+///             return 0;
+///         }
+///     })
+///     .success();
+/// }
+/// ```
+///
+/// The tests are generated in a module named after the function, e.g.
+/// `test_harness::host_clang_o2`. Each dimension is optional, and
+/// defaults to the host target, the compiler found by `cc-rs`, and no
+/// optimization. When the compiler of a combination is not installed, the
+/// C compiler for `assert_c!` or the C++ driver for `assert_cxx!`, the
+/// test is skipped at run time: it passes, with the reason on the
+/// standard error. Compilers or targets
+/// whose names only differ by punctuation, e.g. `clang-18` and
+/// `clang_18`, are rejected, since their tests would have the same name.
+#[proc_macro_attribute]
+pub fn test(
+    args: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    matrix::expand(args.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A construct of the token form that cannot be reproduced faithfully,
+/// reported as a warning at the given span.
+type Warning = (Span, &'static str);
+
+/// Marks the position of a `#{expr}` placeholder in a reconstructed
+/// program. It cannot appear in C code written with tokens.
+const PLACEHOLDER: char = '\u{0}';
+
+/// What is collected while reconstructing a program, besides its text.
+#[derive(Default)]
+struct Collected {
+    warnings: Vec<Warning>,
+    /// The expressions of the `#{expr}` placeholders, in order.
+    placeholders: Vec<TokenStream>,
+    /// The invalid `#inline_c_rs` directives, reported as errors.
+    errors: Vec<(Span, String)>,
+    /// The names of the macros defined with a stringification.
+    stringifying_macros: Vec<String>,
+}
+
+fn expand(language: TokenStream, input: TokenStream) -> TokenStream {
+    let mut collected = Collected::default();
+
+    let program = match string_literal(&input) {
+        // assert_c!(r#"…"#)
+        Some(literal) => {
+            let program = match interpolate(&literal, &mut collected) {
+                Ok(program) => program,
+                Err(error) => return error.to_compile_error(),
+            };
+            let text = match (&program, syn::Lit::new(literal.clone())) {
+                (Some(program), _) => program.clone(),
+                (None, syn::Lit::Str(string)) => string.value(),
+                (None, _) => String::new(),
+            };
+
+            if let Err(error) = validate(&text) {
+                return syn::Error::new(literal.span(), error).to_compile_error();
+            }
+
+            match program {
+                Some(program) => program,
+                None => return program_run(language, quote!(#literal), Vec::new()),
+            }
+        }
+        None => reconstruct(input, &mut collected),
+    };
+
+    if !collected.errors.is_empty() {
+        let errors = collected
+            .errors
+            .into_iter()
+            .map(|(span, message)| syn::Error::new(span, message).to_compile_error());
+
+        return quote!(#(#errors)*);
+    }
+
+    let program = if collected.placeholders.is_empty() {
+        quote!(#program)
+    } else {
+        let program_ident = Ident::new("program", Span::mixed_site());
+        let texts = program.split(PLACEHOLDER).collect::<Vec<_>>();
+        let head = texts[0];
+        let tail = &texts[1..];
+        let placeholders = collected.placeholders;
+
+        // The placeholders of the directive lines are formatted for the
+        // directive grammar.
+        let mut line = String::new();
+        let formats = texts[..placeholders.len()].iter().map(|text| {
+            match text.rfind('\n') {
+                Some(end) => line = text[end + 1..].to_string(),
+                None => line.push_str(text),
+            }
+
+            if line
+                .trim_start()
+                .starts_with(libafl_inline_c_directives::KEYWORD)
+            {
+                quote!(to_directive_value)
+            } else {
+                quote!(to_c_literal)
+            }
+        });
+
+        quote!(
+            &{
+                let mut #program_ident = String::from(#head);
+                #(
+                    #program_ident.push_str(&libafl_inline_c::CLiteral::#formats(&(#placeholders)));
+                    #program_ident.push_str(#tail);
+                )*
+                #program_ident
+            }
+        )
+    };
+
+    program_run(language, program, collected.warnings)
+}
+
+fn program_run(language: TokenStream, program: TokenStream, warnings: Vec<Warning>) -> TokenStream {
+    // There is no stable API to emit warnings from a procedural macro,
+    // so the warnings are reported by using a deprecated constant.
+    let warnings = warnings.into_iter().map(|(span, note)| {
+        quote_spanned!(span=> {
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const token_form_loses_fidelity: () = ();
+            #[allow(clippy::let_unit_value)]
+            let _ = token_form_loses_fidelity;
+        })
+    });
+
+    quote!(
+        {
+            #(#warnings)*
+            libafl_inline_c::run(#language, #program).unwrap_or_else(|error| libafl_inline_c::fail(error))
+        }
+    )
+}
+
+/// Replace the `#{expr}` placeholders of a string literal, or return
+/// `None` if there are none. A `#{` inside a C string or character
+/// literal, or inside a comment, is not a placeholder.
+fn interpolate(
+    literal: &proc_macro2::Literal,
+    collected: &mut Collected,
+) -> syn::Result<Option<String>> {
+    let value = match syn::Lit::new(literal.clone()) {
+        syn::Lit::Str(string) => string.value(),
+        _ => return Ok(None),
+    };
+
+    if !value.contains("#{") {
+        return Ok(None);
+    }
+
+    let mut output = String::new();
+    let mut rest = value.as_str();
+    let mut context = Context::Code;
+
+    while let Some(start) = find_placeholder(rest, &mut context) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+
+        let mut depth = 1;
+        let end = rest
+            .char_indices()
+            .find(|(_, character)| {
+                match character {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => (),
+                }
+
+                depth == 0
+            })
+            .map(|(end, _)| end)
+            .ok_or_else(|| syn::Error::new(literal.span(), "unclosed `#{` placeholder"))?;
+
+        collected.placeholders.push(rest[..end].parse().map_err(
+            |error: proc_macro2::LexError| {
+                syn::Error::new(
+                    literal.span(),
+                    format!("invalid `#{{…}}` placeholder: {}", error),
+                )
+            },
+        )?);
+        output.push(PLACEHOLDER);
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+
+    Ok(Some(output))
+}
+
+/// Where the scanning of C code stands, see [`find_placeholder`].
+#[derive(Clone, Copy)]
+enum Context {
+    Code,
+    String,
+    Character,
+    LineComment,
+    BlockComment,
+}
+
+/// Return the offset of the next `#{` in the code of `text`, outside of
+/// string and character literals and of comments. `context` is where the
+/// scanning starts, and is updated up to the returned offset.
+fn find_placeholder(text: &str, context: &mut Context) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let next = bytes.get(index + 1).copied();
+
+        match (*context, bytes[index]) {
+            (Context::Code, b'#') if next == Some(b'{') => return Some(index),
+            (Context::Code, b'"') => *context = Context::String,
+            (Context::Code, b'\'') => *context = Context::Character,
+            (Context::Code, b'/') if next == Some(b'/') => {
+                *context = Context::LineComment;
+                index += 1;
+            }
+            (Context::Code, b'/') if next == Some(b'*') => {
+                *context = Context::BlockComment;
+                index += 1;
+            }
+            (Context::String | Context::Character, b'\\') => index += 1,
+            (Context::String, b'"') | (Context::Character, b'\'') => *context = Context::Code,
+            // A literal cannot span lines, unlike the rest-of-line value of
+            // a directive, e.g. `#inline_c_rs ENV: NAME=it's`.
+            (Context::String | Context::Character | Context::LineComment, b'\n') => {
+                *context = Context::Code
+            }
+            (Context::BlockComment, b'*') if next == Some(b'/') => {
+                *context = Context::Code;
+                index += 1;
+            }
+            _ => (),
+        }
+
+        index += 1;
+    }
+
+    None
+}
+
+/// Check the `#inline_c_rs` directives of a program. Those holding a
+/// `#{expr}` placeholder can only be checked at runtime.
+fn validate(program: &str) -> Result<(), libafl_inline_c_directives::Error> {
+    let program = program
+        .split('\n')
+        .map(|line| if line.contains(PLACEHOLDER) { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    libafl_inline_c_directives::parse(&program).map(|_| ())
+}
+
+fn expand_file(language: TokenStream, input: TokenStream) -> TokenStream {
+    let path = match string_literal(&input) {
+        Some(path) => path,
+        None => {
+            return quote!(compile_error!(
+                "expected the path of a file, relative to `CARGO_MANIFEST_DIR`, as a string literal"
+            ))
+        }
+    };
+
+    // A file that cannot be read is reported by `include_str!`.
+    if let (syn::Lit::Str(relative_path), Ok(manifest_directory)) = (
+        syn::Lit::new(path.clone()),
+        std::env::var("CARGO_MANIFEST_DIR"),
+    ) {
+        let file = std::path::Path::new(&manifest_directory).join(relative_path.value());
+
+        if let Ok(program) = std::fs::read_to_string(&file) {
+            if let Err(error) = validate(&program) {
+                return syn::Error::new(
+                    path.span(),
+                    format!("{}: {}", relative_path.value(), error),
+                )
+                .to_compile_error();
+            }
+        }
+    }
+
+    quote!(
+        libafl_inline_c::run_file(
+            #language,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/", #path),
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", #path)),
+        )
+        .unwrap_or_else(|error| libafl_inline_c::fail(error))
+    )
+}
+
+/// Return the string literal if it is the only token of the input.
+fn string_literal(input: &TokenStream) -> Option<proc_macro2::Literal> {
+    let mut iterator = input.clone().into_iter();
+
+    match (iterator.next(), iterator.next()) {
+        (Some(TokenTree::Literal(literal)), None) => {
+            let value = literal.to_string();
+
+            if value.starts_with('"') || value.starts_with("r\"") || value.starts_with("r#") {
+                Some(literal)
+            } else {
+                None
+            }
+        }
+
+        _ => None,
+    }
+}
+
+fn reconstruct(input: TokenStream, collected: &mut Collected) -> String {
     use proc_macro2::{Delimiter, Spacing, TokenTree::*};
 
     let mut output = String::new();
@@ -41,107 +410,93 @@ fn reconstruct(input: TokenStream) -> String {
                 let token_value = token.as_char();
 
                 match token_value {
+                    // A preprocessor directive: `#include`, `#define`,
+                    // `#if`, `#pragma`, `#inline_c_rs`…
                     '#' => {
-                        output.push('\n');
-                        output.push(token_value);
-
-                        match iterator.peek() {
-                            // #include …
-                            Some(Ident(include)) if *include == "include" => {
+                        // #{expr}, a value interpolated at runtime.
+                        if let Some(Group(group)) = iterator.peek() {
+                            if group.delimiter() == Delimiter::Brace {
+                                collected.placeholders.push(group.stream());
                                 iterator.next();
+                                output.push(PLACEHOLDER);
+                                output.push(' ');
+
+                                continue;
+                            }
+                        }
+
+                        let hash = token.span().end();
+                        let mut current_line = hash.line;
+
+                        if current_line == 0 {
+                            panic!(
+                                "Preprocessor directives need span locations, which require Rust 1.88 or newer."
+                            );
+                        }
+
+                        // A directive spans all the tokens that start on
+                        // the line where the previous one ended. Groups
+                        // opened on the directive line can thus hold
+                        // several lines; they are joined into a single one.
+                        let mut directive = Vec::new();
+
+                        while let Some(item) = iterator.peek() {
+                            if item.span().start().line != current_line {
+                                break;
+                            }
+
+                            current_line = item.span().end().line;
+                            directive.push(iterator.next().unwrap());
+                        }
 
-                                match iterator.next() {
-                                    // #include <…>
-                                    Some(Punct(punct)) => {
-                                        if punct.as_char() != '<' {
-                                            panic!(
-                                                "Invalid opening token after `#include`, received `{:?}`.",
-                                                token
-                                            )
-                                        }
-
-                                        output.push_str("include <");
-
-                                        loop {
-                                            match iterator.next() {
-                                                Some(Punct(punct)) => {
-                                                    let punct = punct.as_char();
-
-                                                    if punct == '>' {
-                                                        break;
-                                                    }
-
-                                                    output.push(punct)
-                                                }
-
-                                                Some(Ident(ident)) => {
-                                                    output.push_str(&ident.to_string())
-                                                }
-
-                                                token => panic!(
-                                                    "Invalid token in `#include` value, with `{:?}`.",
-                                                    token
-                                                ),
-                                            }
-                                        }
-
-                                        output.push('>');
-                                        output.push('\n');
-                                    }
-
-                                    // #include "…"
-                                    Some(Literal(literal)) => {
-                                        output.push_str("include ");
-                                        output.push_str(&literal.to_string());
-                                        output.push('\n');
-                                    }
-
-                                    Some(token) => panic!(
-                                        "Invalid opening token after `#include`, received `{:?}`.",
-                                        token
-                                    ),
-
-                                    None => panic!("`#include` must be followed by `<` or `\"`."),
-                                }
+                        // `///` and `/** */` comments are turned into
+                        // `#[doc = "…"]` attributes, and `//!` and `/*! */`
+                        // into `#![doc = "…"]`. The code following a
+                        // `/** */` comment on the same line is kept.
+                        let comment_length = match directive.first() {
+                            Some(Group(group)) if group.delimiter() == Delimiter::Bracket => 1,
+                            Some(Punct(bang))
+                                if bang.as_char() == '!'
+                                    && matches!(directive.get(1), Some(Group(group)) if group.delimiter() == Delimiter::Bracket) =>
+                            {
+                                2
                             }
-                            // #define, only available on nightly.
-                            Some(Ident(define)) if *define == "define" || *define == "ifdef" || *define == "else" || *define == "endif" || *define == "elif" => {
-                                #[cfg(not(nightly))]
-                                panic!(
-                                    "`#define` in C is only supported in `libafl_inline_c` with Rust nightly"
-                                );
-
-                                #[cfg(nightly)]
-                                {
-                                    let current_line = define.span().unwrap().start().line();
-                                    output.push_str(&define.to_string());
-                                    iterator.next();
-
-                                    output.push(' ');
-
-                                    loop {
-                                        match iterator.peek() {
-                                            Some(item) => {
-                                                if item.span().unwrap().start().line()
-                                                    == current_line
-                                                {
-                                                    output.push_str(&item.to_string());
-                                                    output.push(' ');
-                                                    iterator.next();
-                                                } else {
-                                                    output.push('\n');
-                                                    break;
-                                                }
-                                            }
-
-                                            None => break,
-                                        }
-                                    }
-                                }
+                            _ => 0,
+                        };
+
+                        if comment_length > 0 {
+                            collected
+                                .warnings
+                                .push((token.span(), COMMENTS_ARE_DROPPED));
+                            output.push_str(&reconstruct(
+                                directive.into_iter().skip(comment_length).collect(),
+                                collected,
+                            ));
+
+                            continue;
+                        }
+
+                        if let (Some(Ident(define)), Some(Ident(name))) =
+                            (directive.first(), directive.get(1))
+                        {
+                            if *define == "define" && stringification(&directive).is_some() {
+                                collected.stringifying_macros.push(name.to_string());
                             }
+                        }
 
-                            _ => (),
+                        let is_inline_c_rs = matches!(directive.first(), Some(Ident(keyword)) if *keyword == "inline_c_rs");
+                        let name_span = directive.get(1).map_or(token.span(), TokenTree::span);
+                        let line = format!("#{}", reconstruct_line(directive, hash, collected));
+
+                        if is_inline_c_rs {
+                            if let Err(error) = validate(&line) {
+                                collected.errors.push((name_span, error.message));
+                            }
                         }
+
+                        output.push('\n');
+                        output.push_str(&line);
+                        output.push('\n');
                     }
 
                     ';' => {
@@ -149,6 +504,12 @@ fn reconstruct(input: TokenStream) -> String {
                         output.push('\n');
                     }
 
+                    '$' => {
+                        collected.warnings.push((token.span(), DOLLAR_IS_SEPARATED));
+                        output.push(token_value);
+                        output.push(' ');
+                    }
+
                     _ => {
                         output.push(token_value);
 
@@ -160,12 +521,24 @@ fn reconstruct(input: TokenStream) -> String {
             }
 
             Some(Ident(ident)) => {
+                if collected.stringifying_macros.contains(&ident.to_string()) {
+                    if let Some(Group(arguments)) = iterator.peek() {
+                        if arguments.delimiter() == Delimiter::Parenthesis
+                            && loses_whitespace(arguments.stream())
+                        {
+                            collected
+                                .warnings
+                                .push((arguments.span(), STRINGIFICATION_IS_NORMALIZED));
+                        }
+                    }
+                }
+
                 output.push_str(&ident.to_string());
                 output.push(' ');
             }
 
             Some(Group(group)) => {
-                let group_output = reconstruct(group.stream());
+                let group_output = reconstruct(group.stream(), collected);
 
                 match group.delimiter() {
                     Delimiter::Parenthesis => {
@@ -209,3 +582,125 @@ fn reconstruct(input: TokenStream) -> String {
 
     output
 }
+
+const COMMENTS_ARE_DROPPED: &str = "comments are dropped from C code written with tokens; use the raw-string form `assert_c!(r#\"…\"#)` to keep them";
+const DOLLAR_IS_SEPARATED: &str = "`$` in C identifiers is separated from the surrounding identifiers in the token form; use the raw-string form `assert_c!(r#\"…\"#)` instead";
+const STRINGIFICATION_IS_NORMALIZED: &str = "the whitespace of the arguments of a stringifying macro is not preserved in the token form; use the raw-string form `assert_c!(r#\"…\"#)` instead";
+
+/// Return the span of the first stringification operator (`#x`, as
+/// opposed to token pasting `a ## b`) of a `#define` directive.
+fn stringification(directive: &[TokenTree]) -> Option<Span> {
+    use proc_macro2::{Spacing, TokenTree::*};
+
+    let mut iterator = directive.iter().peekable();
+
+    while let Some(token) = iterator.next() {
+        if let Punct(punct) = token {
+            if punct.as_char() == '#' {
+                if punct.spacing() == Spacing::Joint {
+                    // `##`
+                    iterator.next();
+                } else if let Some(Ident(_)) = iterator.peek() {
+                    return Some(punct.span());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether the arguments of a macro call may be stringified with another
+/// whitespace than in the source, i.e. whether one of them is not a
+/// single token.
+fn loses_whitespace(arguments: TokenStream) -> bool {
+    use proc_macro2::TokenTree::*;
+
+    let mut length = 0;
+
+    for token in arguments {
+        match token {
+            Punct(comma) if comma.as_char() == ',' => length = 0,
+            Group(_) => return true,
+            _ => {
+                length += 1;
+
+                if length > 1 {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+/// Reconstruct tokens on a single line, so that they stay part of the
+/// same preprocessor directive. Tokens are separated by a space only
+/// when they are separated in the source, which keeps header names
+/// (`<sys/types.h>`), function-like macros (`#define F(x)`) and
+/// stringification intact.
+fn reconstruct_line(
+    tokens: Vec<TokenTree>,
+    mut previous_end: LineColumn,
+    collected: &mut Collected,
+) -> String {
+    use proc_macro2::{Delimiter, TokenTree::*};
+
+    let mut output = String::new();
+    let mut iterator = tokens.into_iter().peekable();
+
+    while let Some(token) = iterator.next() {
+        if token.span().start() != previous_end {
+            output.push(' ');
+        }
+
+        previous_end = token.span().end();
+
+        match token {
+            // #{expr}
+            Punct(punct)
+                if punct.as_char() == '#'
+                    && matches!(iterator.peek(), Some(Group(group)) if group.delimiter() == Delimiter::Brace) =>
+            {
+                if let Some(Group(group)) = iterator.next() {
+                    previous_end = group.span_close().end();
+                    collected.placeholders.push(group.stream());
+                    output.push(PLACEHOLDER);
+                }
+            }
+
+            Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ('(', ')'),
+                    Delimiter::Brace => ('{', '}'),
+                    Delimiter::Bracket => ('[', ']'),
+                    Delimiter::None => {
+                        output.push_str(&reconstruct_line(
+                            group.stream().into_iter().collect(),
+                            previous_end,
+                            collected,
+                        ));
+
+                        continue;
+                    }
+                };
+                let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+                let inner_end = inner.last().map(|token| token.span().end());
+
+                output.push(open);
+                output.push_str(&reconstruct_line(inner, group.span_open().end(), collected));
+
+                if inner_end.is_some_and(|end| end != group.span_close().start()) {
+                    output.push(' ');
+                }
+
+                output.push(close);
+            }
+
+            token => output.push_str(&token.to_string()),
+        }
+    }
+
+    output
+}
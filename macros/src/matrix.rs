@@ -0,0 +1,197 @@
+//! The `#[libafl_inline_c::test]` attribute, which expands a test into
+//! one test per target, compiler and optimization level.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+use std::collections::HashMap;
+use syn::{
+    parse::Parser, punctuated::Punctuated, spanned::Spanned, Expr, ExprLit, ItemFn, Lit,
+    MetaNameValue, Token,
+};
+
+/// The configured dimensions of the matrix. `None` stands for the host
+/// target, the default compiler or the default optimization level.
+struct Matrix {
+    targets: Vec<Option<String>>,
+    compilers: Vec<Option<String>>,
+    opt_levels: Vec<Option<String>>,
+}
+
+impl Matrix {
+    fn parse(args: TokenStream) -> syn::Result<Self> {
+        let mut matrix = Matrix {
+            targets: vec![None],
+            compilers: vec![None],
+            opt_levels: vec![None],
+        };
+
+        for argument in Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse2(args)? {
+            let values = list(&argument.value)?;
+
+            if argument.path.is_ident("targets") {
+                matrix.targets = values
+                    .into_iter()
+                    .map(|target| if target == "host" { None } else { Some(target) })
+                    .collect();
+            } else if argument.path.is_ident("compilers") {
+                matrix.compilers = values.into_iter().map(Some).collect();
+            } else if argument.path.is_ident("opt_levels") {
+                for value in &values {
+                    if !["0", "1", "2", "3", "s", "z"].contains(&value.as_str()) {
+                        return Err(syn::Error::new(
+                            argument.value.span(),
+                            format!(
+                                "invalid optimization level `{}`, expected one of 0, 1, 2, 3, \"s\" or \"z\"",
+                                value
+                            ),
+                        ));
+                    }
+                }
+
+                matrix.opt_levels = values.into_iter().map(Some).collect();
+            } else {
+                return Err(syn::Error::new(
+                    argument.path.span(),
+                    "unknown argument, expected `targets`, `compilers` or `opt_levels`",
+                ));
+            }
+        }
+
+        Ok(matrix)
+    }
+}
+
+/// Read `["a", "b"]` or `[0, 2]` as a list of strings.
+fn list(value: &Expr) -> syn::Result<Vec<String>> {
+    let elements = match value {
+        Expr::Array(array) => &array.elems,
+        value => {
+            return Err(syn::Error::new(
+                value.span(),
+                "expected a list, e.g. `[\"gcc\", \"clang\"]`",
+            ))
+        }
+    };
+
+    elements
+        .iter()
+        .map(|element| match element {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(string),
+                ..
+            }) => Ok(string.value()),
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(integer),
+                ..
+            }) => Ok(integer.base10_digits().to_string()),
+            element => Err(syn::Error::new(
+                element.span(),
+                "expected a string or an integer literal",
+            )),
+        })
+        .collect()
+}
+
+pub(crate) fn expand(args: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let matrix = Matrix::parse(args)?;
+    let function: ItemFn = syn::parse2(item)?;
+
+    if !function.sig.inputs.is_empty() {
+        return Err(syn::Error::new(
+            function.sig.inputs.span(),
+            "a test function cannot take arguments",
+        ));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    let name = &sig.ident;
+    let output = &sig.output;
+    let guard = Ident::new("matrix", Span::mixed_site());
+    let mut tests = Vec::new();
+    let mut names = HashMap::new();
+
+    for target in &matrix.targets {
+        for compiler in &matrix.compilers {
+            let entry = (target.as_deref(), compiler.as_deref());
+
+            if let Some((other_target, other_compiler)) = names.insert(
+                (
+                    identifier(target.as_deref().unwrap_or("host")),
+                    identifier(compiler.as_deref().unwrap_or("default")),
+                ),
+                entry,
+            ) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "the tests for the target `{}` with the compiler `{}` and for the target `{}` with the compiler `{}` would have the same name",
+                        other_target.unwrap_or("host"),
+                        other_compiler.unwrap_or("default"),
+                        target.as_deref().unwrap_or("host"),
+                        compiler.as_deref().unwrap_or("default"),
+                    ),
+                ));
+            }
+
+            for opt_level in &matrix.opt_levels {
+                let test_name = format_ident!(
+                    "{}_{}_o{}",
+                    identifier(target.as_deref().unwrap_or("host")),
+                    identifier(compiler.as_deref().unwrap_or("default")),
+                    opt_level.as_deref().unwrap_or("0"),
+                );
+                let target = option(target);
+                let compiler = option(compiler);
+                let opt_level = option(opt_level);
+
+                tests.push(quote!(
+                    #[::core::prelude::v1::test]
+                    #(#attrs)*
+                    fn #test_name() #output {
+                        let #guard = libafl_inline_c::install_matrix(#target, #compiler, #opt_level);
+
+                        libafl_inline_c::skippable(move || #block)
+                    }
+                ));
+            }
+        }
+    }
+
+    Ok(quote!(
+        #[cfg(test)]
+        #vis mod #name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#tests)*
+        }
+    ))
+}
+
+fn option(value: &Option<String>) -> TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
+
+/// Turn a target triple or a compiler path into a part of an identifier.
+fn identifier(value: &str) -> String {
+    value
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
@@ -1,11 +1,40 @@
-use std::{fs, path::PathBuf, process::Command};
+use crate::{
+    bench::Statistics, blocking, compiler::Compiler, coverage::Instrumentation,
+    execution::Execution, instructions::InstructionCounter, limit::Resource, skip::Skipped,
+    snapshot,
+};
+use assert_cmd::assert::OutputAssertExt;
+use regex::Regex;
+use std::{
+    ffi::{CString, OsStr},
+    fs,
+    future::Future,
+    io,
+    os::raw::{c_char, c_int},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::Instant,
+};
+
+/// The number of bytes of each output of the program that are captured
+/// by default.
+const DEFAULT_OUTPUT_LIMIT: usize = 16 * 1024 * 1024;
 
 /// `Assert` is a wrapper around the [`assert_cmd::assert::Assert`]
 /// struct.
 pub struct Assert {
-    command: assert_cmd::Command,
+    command: Command,
+    env_clear: bool,
+    output_limit: usize,
+    limits: Vec<(Resource, u64)>,
+    fixtures: Vec<(PathBuf, Vec<u8>)>,
+    redactions: Vec<(Regex, String)>,
+    warm_up: usize,
+    instrumentation: Option<Instrumentation>,
     files_to_remove: Option<Vec<PathBuf>>,
     output_path: PathBuf,
+    compiler: Compiler,
+    shared: bool,
 }
 
 impl Assert {
@@ -13,16 +42,35 @@ impl Assert {
         command: Command,
         files_to_remove: Option<Vec<PathBuf>>,
         output_path: PathBuf,
+        compiler: Compiler,
+        shared: bool,
     ) -> Self {
         Self {
-            command: assert_cmd::Command::from_std(command),
-            files_to_remove: files_to_remove,
-            output_path: output_path,
+            command,
+            env_clear: false,
+            output_limit: DEFAULT_OUTPUT_LIMIT,
+            limits: Vec::new(),
+            fixtures: Vec::new(),
+            redactions: Vec::new(),
+            warm_up: 1,
+            instrumentation: None,
+            files_to_remove,
+            output_path,
+            compiler,
+            shared,
         }
     }
 
     pub fn assert(&mut self) -> assert_cmd::assert::Assert {
-        self.command.assert()
+        self.output()
+            .unwrap_or_else(|error| {
+                panic!(
+                    "Failed to spawn `{}`: {}",
+                    self.output_path.display(),
+                    error
+                )
+            })
+            .assert()
     }
 
     /// Shortcut to `self.assert().success()`.
@@ -35,10 +83,388 @@ impl Assert {
         self.assert().failure()
     }
 
-    /// Return the path that the executable was compiled to. Useful for shared object/dll compilation. 
+    /// Set an environment variable of the executable.
+    pub fn env(&mut self, name: &str, value: &str) -> &mut Self {
+        self.command.env(name, value);
+        self
+    }
+
+    /// Remove an environment variable inherited by the executable.
+    pub fn env_remove(&mut self, name: &str) -> &mut Self {
+        self.command.env_remove(name);
+        self
+    }
+
+    /// Run the executable with an empty environment, besides the
+    /// variables set afterwards with [`Assert::env`].
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.command.env_clear();
+        self.env_clear = true;
+        self
+    }
+
+    /// Capture at most `bytes` bytes of each output of the executable,
+    /// so that a runaway program cannot exhaust the memory of the test.
+    /// The rest of the output is discarded. It defaults to 16 MiB.
+    pub fn output_limit(&mut self, bytes: usize) -> &mut Self {
+        self.output_limit = bytes;
+        self
+    }
+
+    /// Limit a resource of the executable, like the `RLIMIT` directive.
+    /// The soft limit is set on Unix, without raising it above the hard
+    /// limit. A program killed for exceeding its CPU time or file size
+    /// limit ends with
+    /// [`Outcome::LimitExceeded`][crate::Outcome::LimitExceeded]. It has
+    /// no effect on other platforms.
+    pub fn rlimit(&mut self, resource: Resource, limit: u64) -> &mut Self {
+        apply_limit(&mut self.command, resource, limit);
+        self.limits.push((resource, limit));
+        self
+    }
+
+    /// Write a file in the working directory of the executable before it
+    /// runs, like the `FIXTURE` directive. The path is relative to the
+    /// working directory, which is new for each run.
+    pub fn fixture<P: AsRef<Path>, C: AsRef<[u8]>>(&mut self, path: P, contents: C) -> &mut Self {
+        self.fixtures
+            .push((path.as_ref().to_path_buf(), contents.as_ref().to_vec()));
+        self
+    }
+
+    /// Run the executable in a new working directory, and return its
+    /// output, exit outcome, the time and memory it used, and the files it
+    /// wrote.
+    ///
+    /// The coverage of a program built with the `SOURCE_COVERAGE`
+    /// directive is collected, see
+    /// [`Execution::coverage`][crate::Execution::coverage].
+    pub fn execute(&mut self) -> io::Result<Execution> {
+        self.start()?()
+    }
+
+    /// Like [`Assert::execute`], but wait for the executable in another
+    /// thread, so that the threads of the executor are not blocked. The
+    /// executable is spawned when this is called, and it works with any
+    /// executor.
+    pub fn execute_async(
+        &mut self,
+    ) -> impl Future<Output = io::Result<Execution>> + Send + 'static {
+        let started = self.start();
+
+        blocking::spawn(move || started?())
+    }
+
+    /// Spawn the executable, and return the function waiting for its
+    /// execution and collecting its coverage.
+    fn start(&mut self) -> io::Result<impl FnOnce() -> io::Result<Execution> + Send + 'static> {
+        let profile = match &self.instrumentation {
+            Some(instrumentation) => Some(instrumentation.prepare(&mut self.command)?),
+            None => None,
+        };
+        let running = Execution::start(
+            &mut self.command,
+            self.output_limit,
+            &self.limits,
+            &self.fixtures,
+        )?;
+        let instrumentation = self.instrumentation.clone();
+
+        if let Some(instrumentation) = &instrumentation {
+            // The profile directory is removed with `profile`, so other
+            // runs, e.g. with a tool, must not write there.
+            for name in instrumentation.variables() {
+                self.command.env_remove(name);
+            }
+        }
+
+        Ok(move || {
+            let execution = running.wait()?;
+
+            match (instrumentation, profile) {
+                (Some(instrumentation), Some(profile)) => {
+                    Ok(execution.with_coverage(instrumentation.collect(profile.path())?))
+                }
+                _ => Ok(execution),
+            }
+        })
+    }
+
+    /// Replace the matches of the regular expression `pattern` in the
+    /// outputs compared by [`Assert::snapshot`], e.g. `[0-9]+ ms` by
+    /// `[DURATION]`. The replacement can refer to the groups of the
+    /// pattern, e.g. `$1`. Addresses such as `0x7ffd5e8c3a1c`, or
+    /// `00007FFD5E8C3A1C` as printed by MSVC, are replaced by `[ADDRESS]`
+    /// beforehand.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid regular expression.
+    pub fn redact(&mut self, pattern: &str, replacement: &str) -> &mut Self {
+        let regex = Regex::new(pattern)
+            .unwrap_or_else(|error| panic!("Invalid redaction `{}`: {}", pattern, error));
+
+        self.redactions.push((regex, replacement.to_string()));
+        self
+    }
+
+    /// Run the executable, and compare its outcome and outputs with the
+    /// snapshot `name`, stored in the `snapshots` directory next to the
+    /// source of the test, e.g. `tests/snapshots/parser__name.snap` for
+    /// a test of `tests/parser.rs`. With
+    /// `INLINE_C_RS_UPDATE_SNAPSHOTS=1`, the snapshot is written instead.
+    /// The execution is returned for further assertions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the execution does not match the snapshot, or if the
+    /// snapshot does not exist.
+    #[track_caller]
+    pub fn snapshot(&mut self, name: &str) -> Execution {
+        let path = snapshot::path(std::panic::Location::caller().file(), name);
+        let execution = self.execute().unwrap_or_else(|error| {
+            panic!(
+                "Failed to spawn `{}`: {}",
+                self.output_path.display(),
+                error
+            )
+        });
+
+        if let Err(message) = snapshot::check(
+            &path,
+            &snapshot::render(&execution, &self.redactions),
+            snapshot::update(),
+        ) {
+            panic!("{}", message);
+        }
+
+        execution
+    }
+
+    /// Set the number of runs of [`Assert::bench`] and
+    /// [`Assert::bench_fuzzer`] that are not measured, to warm up the
+    /// caches. It defaults to 1.
+    pub fn warm_up(&mut self, runs: usize) -> &mut Self {
+        self.warm_up = runs;
+        self
+    }
+
+    /// Run the executable `iterations` times after the warm-up runs, and
+    /// return the statistics of their wall times. An
+    /// [`io::ErrorKind::InvalidInput`] error is returned if `iterations`
+    /// is 0.
+    pub fn bench(&mut self, iterations: usize) -> io::Result<Statistics> {
+        if iterations == 0 {
+            return Err(no_runs());
+        }
+
+        for _ in 0..self.warm_up {
+            self.execute()?;
+        }
+
+        let samples = (0..iterations)
+            .map(|_| self.execute().map(|execution| execution.wall_time()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Statistics::new(samples))
+    }
+
+    /// Load the library built with the `SHARED` directive in the test
+    /// process, call its `LLVMFuzzerInitialize` function if any, and then
+    /// call `LLVMFuzzerTestOneInput` `iterations` times with each input,
+    /// after the warm-up calls. The statistics of the wall times of the
+    /// calls are returned, to measure the executions per second of a
+    /// fuzzing harness. The harness runs in the test process, without its
+    /// environment, limits or working directory, and a crash of the
+    /// harness crashes the test. An [`io::ErrorKind::InvalidInput`] error
+    /// is returned if `inputs` is empty or `iterations` is 0.
+    pub fn bench_fuzzer<I: AsRef<[u8]>>(
+        &mut self,
+        inputs: &[I],
+        iterations: usize,
+    ) -> Result<Statistics, Box<dyn std::error::Error>> {
+        if !self.shared {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Benchmarking `LLVMFuzzerTestOneInput` requires a library built with the `SHARED` directive",
+            )));
+        }
+
+        if inputs.is_empty() || iterations == 0 {
+            return Err(Box::new(no_runs()));
+        }
+
+        // SAFETY: the library is the harness under test, whose
+        // initializers and functions are trusted like its executions.
+        let samples = unsafe {
+            let library = libloading::Library::new(&self.output_path)?;
+            let test_one_input = library.get::<unsafe extern "C" fn(*const u8, usize) -> c_int>(
+                b"LLVMFuzzerTestOneInput\0",
+            )?;
+
+            if let Ok(initialize) = library
+                .get::<unsafe extern "C" fn(*mut c_int, *mut *mut *mut c_char) -> c_int>(
+                    b"LLVMFuzzerInitialize\0",
+                )
+            {
+                let program = CString::new(self.output_path.to_string_lossy().into_owned())?;
+                let mut arguments = [program.as_ptr() as *mut c_char, std::ptr::null_mut()];
+                let mut argc = 1;
+                let mut argv = arguments.as_mut_ptr();
+
+                initialize(&mut argc, &mut argv);
+            }
+
+            let mut samples = Vec::with_capacity(inputs.len() * iterations);
+
+            for input in inputs {
+                let input = input.as_ref();
+
+                for _ in 0..self.warm_up {
+                    test_one_input(input.as_ptr(), input.len());
+                }
+
+                for _ in 0..iterations {
+                    let start = Instant::now();
+                    test_one_input(input.as_ptr(), input.len());
+                    samples.push(start.elapsed());
+                }
+            }
+
+            samples
+        };
+
+        Ok(Statistics::new(samples))
+    }
+
+    /// Run the executable once with the first instruction counter that is
+    /// installed, see [`InstructionCounter::detect`], and return the
+    /// number of instructions it executed in user space. Unlike the wall
+    /// time, the count does not depend on the load of the machine. When no
+    /// counter is installed, a [`Skipped`] error is returned.
+    pub fn count_instructions(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+        match InstructionCounter::detect() {
+            Some(counter) => self.count_instructions_with(counter),
+            None => Err(Box::new(Skipped::new(String::from(
+                "Counting instructions requires `valgrind` or `perf`",
+            )))),
+        }
+    }
+
+    /// Like [`Assert::count_instructions`], with the given counter.
+    pub fn count_instructions_with(
+        &mut self,
+        counter: InstructionCounter,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let directory = tempfile::Builder::new().prefix("inline-c-rs-").tempdir()?;
+        let report = directory.path().join("instructions.out");
+        let execution = self.execute_with(&counter.command(&report))?;
+
+        fs::read_to_string(&report)
+            .ok()
+            .and_then(|report| counter.parse(&report))
+            .ok_or_else(|| {
+                format!(
+                    "{:?} did not count the instructions ({}): {}",
+                    counter,
+                    execution.outcome(),
+                    String::from_utf8_lossy(execution.stderr())
+                )
+                .into()
+            })
+    }
+
+    pub(crate) fn instrument(&mut self, instrumentation: Instrumentation) {
+        self.instrumentation = Some(instrumentation);
+    }
+
+    /// Start the executable, built with the `FORKSERVER` directive, as a
+    /// forkserver, and return its driver. The forkserver runs in a new
+    /// working directory holding the fixtures, with the environment and
+    /// the limits of the executable.
+    #[cfg(unix)]
+    pub fn forkserver(&mut self) -> io::Result<crate::Forkserver> {
+        crate::Forkserver::spawn(self.command_with::<&str>(&[]), &self.limits, &self.fixtures)
+    }
+
+    /// Run the executable with a tool, e.g. `valgrind`, given as its
+    /// program and the arguments preceding the executable. The tool runs
+    /// in the environment, working directory and limits of the executable.
+    pub(crate) fn execute_with<S: AsRef<OsStr>>(&mut self, tool: &[S]) -> io::Result<Execution> {
+        Execution::run(
+            &mut self.command_with(tool),
+            self.output_limit,
+            &self.limits,
+            &self.fixtures,
+        )
+    }
+
+    /// A new command running the executable, preceded by the tool if any,
+    /// with the environment and the limits of the executable.
+    pub(crate) fn command_with<S: AsRef<OsStr>>(&self, tool: &[S]) -> Command {
+        let mut arguments = tool
+            .iter()
+            .map(|argument| argument.as_ref())
+            .chain(Some(self.command.get_program()))
+            .chain(self.command.get_args());
+        let mut command = Command::new(arguments.next().unwrap_or_default());
+        command.args(arguments);
+
+        if self.env_clear {
+            command.env_clear();
+        }
+
+        for (name, value) in self.command.get_envs() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+
+        for (resource, limit) in &self.limits {
+            apply_limit(&mut command, *resource, *limit);
+        }
+
+        command
+    }
+
+    /// Run the executable and collect its output.
+    pub(crate) fn output(&mut self) -> io::Result<Output> {
+        self.execute().map(Output::from)
+    }
+
+    /// Return the path that the executable was compiled to. Useful for shared object/dll compilation.
     pub fn output_path(&self) -> &PathBuf {
         &self.output_path
     }
+
+    /// Return the compiler that built the executable, with its detected
+    /// family and version.
+    pub fn compiler(&self) -> &Compiler {
+        &self.compiler
+    }
+}
+
+/// Limit a resource of the command, on Unix.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn apply_limit(command: &mut Command, resource: Resource, limit: u64) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: setting a resource limit is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || crate::limit::set(resource, limit));
+        }
+    }
+}
+
+/// The error of a benchmark without runs.
+fn no_runs() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "A benchmark needs at least one run",
+    )
 }
 
 impl Drop for Assert {
@@ -0,0 +1,180 @@
+use crate::{
+    assert::Assert,
+    run::{run_with, BoxError, Config, Language},
+};
+use jobserver::Client;
+use lazy_static::lazy_static;
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+lazy_static! {
+    /// The jobserver of cargo or make, from `CARGO_MAKEFLAGS`, `MAKEFLAGS`
+    /// or `MFLAGS`, if any.
+    static ref JOBSERVER: Option<Client> = {
+        // SAFETY: the file descriptors of the jobserver are inherited from
+        // cargo or make, and are only read and written through the client.
+        unsafe { Client::from_env() }
+    };
+}
+
+/// Compile the snippets concurrently with `config`, and return their
+/// results in the same order.
+///
+/// At most `jobs` snippets are compiled at once, which defaults to the
+/// available parallelism. Within this limit, the process compiles one
+/// snippet with the token it implicitly holds, and each further compilation
+/// needs a token of the jobserver, if any, so that the tests run by cargo
+/// do not start more compilers together than `cargo test -j` allows.
+pub(crate) fn run(
+    config: &Config,
+    snippets: Vec<(Language, String)>,
+    jobs: Option<usize>,
+) -> Vec<Result<Assert, BoxError>> {
+    run_with_jobserver(config, snippets, jobs, JOBSERVER.as_ref())
+}
+
+fn run_with_jobserver(
+    config: &Config,
+    snippets: Vec<(Language, String)>,
+    jobs: Option<usize>,
+    jobserver: Option<&Client>,
+) -> Vec<Result<Assert, BoxError>> {
+    let workers = jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+        .clamp(1, snippets.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(snippets.iter().map(|_| None).collect::<Vec<_>>());
+
+    let compile = |index: usize| {
+        let (language, program) = &snippets[index];
+        let result = run_with(*language, program, config);
+
+        results.lock().unwrap_or_else(|error| error.into_inner())[index] = Some(result);
+    };
+    let claim =
+        || Some(next.fetch_add(1, Ordering::SeqCst)).filter(|index| *index < snippets.len());
+
+    // The helper thread of the jobserver acquires a token for each worker,
+    // which waits for it without polling. The helper stops when it is
+    // dropped, and the workers still waiting then return.
+    let (sender, receiver) = mpsc::channel();
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        let helper = jobserver.and_then(|jobserver| {
+            jobserver
+                .clone()
+                .into_helper_thread(move |token| {
+                    let _ = sender.send(token);
+                })
+                .ok()
+        });
+
+        for _ in 1..workers {
+            if let Some(helper) = &helper {
+                helper.request_token();
+            }
+
+            scope.spawn(|| {
+                // The token is released when it is dropped, after the
+                // compilations. A worker that cannot get a token leaves
+                // the snippets to the others.
+                let _token = if jobserver.is_some() {
+                    match receiver
+                        .lock()
+                        .unwrap_or_else(|error| error.into_inner())
+                        .recv()
+                    {
+                        Ok(Ok(token)) => Some(token),
+                        _ => return,
+                    }
+                } else {
+                    None
+                };
+
+                while let Some(index) = claim() {
+                    compile(index);
+                }
+            });
+        }
+
+        // The current thread holds the implicit token of the process.
+        while let Some(index) = claim() {
+            compile(index);
+        }
+
+        drop(helper);
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|error| error.into_inner())
+        .into_iter()
+        .map(|result| result.expect("Every snippet is compiled"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::CompilationError;
+
+    fn snippets() -> Vec<(Language, String)> {
+        (0..4)
+            .map(|code| (Language::C, format!("int main() {{ return {}; }}", code)))
+            .chain(Some((Language::Cxx, String::from("int main() { return"))))
+            .collect()
+    }
+
+    fn check(results: Vec<Result<Assert, BoxError>>) {
+        assert_eq!(results.len(), 5);
+
+        for (code, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(mut assert) => {
+                    assert.assert().code(code as i32);
+                }
+                Err(error) => {
+                    assert_eq!(code, 4);
+                    assert!(error.is::<CompilationError>());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        check(run(&Config::new(), snippets(), Some(2)));
+        check(run(&Config::new(), snippets(), Some(1)));
+        assert!(run(&Config::new(), Vec::new(), None).is_empty());
+    }
+
+    #[test]
+    fn test_run_with_jobserver() {
+        // The current thread compiles every snippet when the jobserver has
+        // no token to spare.
+        let jobserver = Client::new(0).unwrap();
+
+        check(run_with_jobserver(
+            &Config::new(),
+            snippets(),
+            Some(4),
+            Some(&jobserver),
+        ));
+
+        let jobserver = Client::new(2).unwrap();
+
+        check(run_with_jobserver(
+            &Config::new(),
+            snippets(),
+            Some(4),
+            Some(&jobserver),
+        ));
+    }
+}
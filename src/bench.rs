@@ -0,0 +1,219 @@
+use std::{fmt, fmt::Display, time::Duration};
+
+/// The wall times of the runs of a benchmark, returned by
+/// [`Assert::bench`][crate::Assert::bench] and
+/// [`Assert::bench_fuzzer`][crate::Assert::bench_fuzzer].
+#[derive(Clone, Debug)]
+pub struct Statistics {
+    /// The sorted samples.
+    samples: Vec<Duration>,
+}
+
+impl Statistics {
+    pub(crate) fn new(mut samples: Vec<Duration>) -> Self {
+        assert!(!samples.is_empty(), "A benchmark needs at least one run");
+        samples.sort();
+
+        Self { samples }
+    }
+
+    /// The number of measured runs, besides the warm-up runs.
+    pub fn iterations(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The fastest run.
+    pub fn min(&self) -> Duration {
+        self.samples[0]
+    }
+
+    /// The slowest run.
+    pub fn max(&self) -> Duration {
+        self.samples[self.samples.len() - 1]
+    }
+
+    /// The median of the runs, which is less sensitive to outliers than
+    /// the mean.
+    pub fn median(&self) -> Duration {
+        let middle = self.samples.len() / 2;
+
+        if self.samples.len().is_multiple_of(2) {
+            (self.samples[middle - 1] + self.samples[middle]) / 2
+        } else {
+            self.samples[middle]
+        }
+    }
+
+    /// The mean of the runs.
+    pub fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+
+    /// The standard deviation of the runs.
+    pub fn stddev(&self) -> Duration {
+        if self.samples.len() < 2 {
+            return Duration::ZERO;
+        }
+
+        let mean = self.mean().as_secs_f64();
+        let variance = self
+            .samples
+            .iter()
+            .map(|sample| (sample.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / (self.samples.len() - 1) as f64;
+
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    /// The number of runs per second, from the mean.
+    pub fn executions_per_second(&self) -> f64 {
+        1.0 / self.mean().as_secs_f64()
+    }
+
+    /// Assert that the median run is faster than `threshold`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the statistics if the median exceeds `threshold`.
+    #[track_caller]
+    pub fn assert_median_below(&self, threshold: Duration) -> &Self {
+        assert!(
+            self.median() <= threshold,
+            "The median run exceeds the threshold of {:?}: {}",
+            threshold,
+            self
+        );
+
+        self
+    }
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {:?}, median {:?}, mean {:?}, stddev {:?} over {} runs ({:.1} executions per second)",
+            self.min(),
+            self.median(),
+            self.mean(),
+            self.stddev(),
+            self.iterations(),
+            self.executions_per_second()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Language};
+
+    #[test]
+    fn test_statistics() {
+        let statistics = Statistics::new(
+            [4, 2, 8, 6]
+                .iter()
+                .map(|milliseconds| Duration::from_millis(*milliseconds))
+                .collect(),
+        );
+
+        assert_eq!(statistics.iterations(), 4);
+        assert_eq!(statistics.min(), Duration::from_millis(2));
+        assert_eq!(statistics.max(), Duration::from_millis(8));
+        assert_eq!(statistics.median(), Duration::from_millis(5));
+        assert_eq!(statistics.mean(), Duration::from_millis(5));
+        assert_eq!(statistics.stddev().as_micros(), 2581);
+        assert_eq!(statistics.executions_per_second().round(), 200.0);
+
+        statistics.assert_median_below(Duration::from_millis(5));
+
+        let result = std::panic::catch_unwind(|| {
+            statistics.assert_median_below(Duration::from_millis(4));
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bench() {
+        let statistics = run(Language::C, "int main() { return 0; }")
+            .unwrap()
+            .warm_up(2)
+            .bench(5)
+            .unwrap();
+
+        assert_eq!(statistics.iterations(), 5);
+        assert!(statistics.min() <= statistics.median());
+        assert!(statistics.median() <= statistics.max());
+        statistics.assert_median_below(Duration::from_secs(10));
+
+        let error = run(Language::C, "int main() { return 0; }")
+            .unwrap()
+            .bench(0)
+            .expect_err("A benchmark needs at least one run");
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_bench_fuzzer() {
+        let program = r#"
+            #include <stdint.h>
+            #include <stddef.h>
+            #include <stdlib.h>
+
+            static int initialized = 0;
+
+            int LLVMFuzzerInitialize(int* argc, char*** argv) {
+                initialized = *argc == 1 && (*argv)[0] != NULL;
+
+                return 0;
+            }
+
+            int LLVMFuzzerTestOneInput(const uint8_t* data, size_t size) {
+                if (!initialized) {
+                    abort();
+                }
+
+                volatile size_t sum = 0;
+
+                for (size_t i = 0; i < size; i++) {
+                    sum += data[i];
+                }
+
+                return 0;
+            }
+        "#;
+
+        let statistics = run(Language::C, &format!("#inline_c_rs SHARED\n{}", program))
+            .unwrap()
+            .bench_fuzzer(&[&b"a"[..], &[0; 4096][..]], 100)
+            .unwrap();
+
+        assert_eq!(statistics.iterations(), 200);
+        assert!(statistics.executions_per_second() > 0.0);
+
+        let error = run(
+            Language::C,
+            &format!("{}\nint main() {{ return 0; }}", program),
+        )
+        .unwrap()
+        .bench_fuzzer(&[b"a"], 1)
+        .expect_err("Executables cannot be loaded");
+
+        assert!(error.to_string().contains("`SHARED`"));
+
+        let error = run(Language::C, &format!("#inline_c_rs SHARED\n{}", program))
+            .unwrap()
+            .bench_fuzzer::<&[u8]>(&[], 1)
+            .expect_err("A benchmark needs at least one input");
+
+        assert_eq!(
+            error
+                .downcast_ref::<std::io::Error>()
+                .map(|error| error.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// The state shared by a [`Blocking`] future and its thread.
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// A future completed by a blocking function running in its own thread,
+/// so that it does not tie up the threads of the executor. It works with
+/// any executor, since it only relies on the waker.
+pub(crate) struct Blocking<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// Run `function` in a new thread, and return a future of its result. A
+/// panic of the function is resumed when the future is polled.
+pub(crate) fn spawn<T, F>(function: F) -> Blocking<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let thread_shared = Arc::clone(&shared);
+
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(function));
+        let mut shared = thread_shared
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        shared.result = Some(result);
+
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+
+    Blocking { shared }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        let mut shared = self
+            .shared
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        match shared.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => {
+                shared.waker = Some(context.waker().clone());
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Run a future to completion on the current thread, for the tests.
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_spawn() {
+        assert_eq!(
+            block_on(spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+
+                42
+            })),
+            42
+        );
+
+        let result = panic::catch_unwind(|| block_on(spawn(|| panic!("Failed"))));
+
+        assert!(result.is_err());
+    }
+}
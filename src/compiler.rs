@@ -0,0 +1,320 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+/// The family of a compiler, which decides the syntax of its arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompilerFamily {
+    /// GCC, or a compiler with a GCC-compatible driver.
+    Gcc,
+    /// Clang, including `clang-cl`.
+    Clang,
+    /// The Microsoft Visual C++ compiler.
+    Msvc,
+}
+
+impl Display for CompilerFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Gcc => write!(f, "gcc"),
+            Self::Clang => write!(f, "clang"),
+            Self::Msvc => write!(f, "msvc"),
+        }
+    }
+}
+
+/// The version of a compiler, e.g. `18.1.3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompilerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CompilerVersion {
+    /// Parse `major[.minor[.patch]]`.
+    fn parse(version: &str) -> Option<Self> {
+        let mut numbers = version.split('.').map(str::parse::<u32>);
+
+        Some(Self {
+            major: numbers.next()?.ok()?,
+            minor: numbers.next().unwrap_or(Ok(0)).ok()?,
+            patch: numbers.next().unwrap_or(Ok(0)).ok()?,
+        })
+    }
+}
+
+impl Display for CompilerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The compiler that built a program.
+#[derive(Clone, Debug)]
+pub struct Compiler {
+    path: PathBuf,
+    family: CompilerFamily,
+    version: Option<CompilerVersion>,
+}
+
+impl Compiler {
+    /// Describe the compiler found by `cc-rs`, detecting its version.
+    pub(crate) fn detect(tool: &cc::Tool) -> Self {
+        let family = if tool.is_like_clang() {
+            CompilerFamily::Clang
+        } else if tool.is_like_msvc() {
+            CompilerFamily::Msvc
+        } else {
+            CompilerFamily::Gcc
+        };
+
+        lazy_static! {
+            static ref VERSIONS: Mutex<HashMap<PathBuf, Option<CompilerVersion>>> =
+                Mutex::new(HashMap::new());
+        }
+
+        let version = *VERSIONS
+            .lock()
+            .unwrap()
+            .entry(tool.path().to_path_buf())
+            .or_insert_with(|| detect_version(tool.path(), family));
+
+        Self {
+            path: tool.path().to_path_buf(),
+            family,
+            version,
+        }
+    }
+
+    /// The path or the name of the compiler executable.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn family(&self) -> CompilerFamily {
+        self.family
+    }
+
+    /// The version of the compiler, if it could be detected.
+    pub fn version(&self) -> Option<CompilerVersion> {
+        self.version
+    }
+}
+
+fn detect_version(path: &Path, family: CompilerFamily) -> Option<CompilerVersion> {
+    lazy_static! {
+        static ref AFTER_VERSION: Regex =
+            Regex::new(r"[Vv]ersion (?P<version>\d+(\.\d+){0,2})").unwrap();
+        static ref ANY: Regex = Regex::new(r"(?P<version>\d+(\.\d+){1,2})").unwrap();
+    }
+
+    // `cl` prints its version in its banner, on stderr, when run without
+    // arguments.
+    let output = match family {
+        CompilerFamily::Msvc => Command::new(path).output().ok()?,
+        _ => Command::new(path).arg("--version").output().ok()?,
+    };
+    let output = match family {
+        CompilerFamily::Msvc => String::from_utf8_lossy(&output.stderr).into_owned(),
+        _ => String::from_utf8_lossy(&output.stdout).into_owned(),
+    };
+    let first_line = output.lines().next()?;
+
+    // `clang version 18.1.3 (…)`, or `gcc (GCC) 13.2.1 20230801`.
+    AFTER_VERSION
+        .captures(first_line)
+        .or_else(|| ANY.captures_iter(first_line).last())
+        .and_then(|captures| CompilerVersion::parse(&captures["version"]))
+}
+
+/// A minimum compiler version, e.g. `clang>=15 gcc>=12`. It is met when
+/// the compiler belongs to one of the families and is recent enough.
+pub(crate) struct Requirement(Vec<(Option<CompilerFamily>, CompilerVersion)>);
+
+impl Requirement {
+    pub(crate) fn parse(requirement: &str) -> Result<Self, String> {
+        lazy_static! {
+            static ref REGEX: Regex =
+                Regex::new(r"^(?P<family>gcc|clang|msvc)?>=(?P<version>\d+(\.\d+){0,2})$").unwrap();
+        }
+
+        requirement
+            .split(|character: char| character.is_ascii_whitespace() || character == ',')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let captures = REGEX.captures(part).ok_or_else(|| {
+                    format!(
+                        "Invalid compiler requirement `{}`, expected e.g. `clang>=15`",
+                        part
+                    )
+                })?;
+                let family = captures.name("family").map(|family| match family.as_str() {
+                    "gcc" => CompilerFamily::Gcc,
+                    "clang" => CompilerFamily::Clang,
+                    _ => CompilerFamily::Msvc,
+                });
+
+                Ok((
+                    family,
+                    CompilerVersion::parse(&captures["version"]).unwrap(),
+                ))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub(crate) fn is_met_by(&self, compiler: &Compiler) -> bool {
+        self.0.iter().any(|(family, minimum)| {
+            family.is_none_or(|family| family == compiler.family)
+                && compiler.version.is_some_and(|version| version >= *minimum)
+        })
+    }
+}
+
+/// Return the C++ driver of a C compiler, e.g. `clang++-18` for
+/// `clang-18` or `x86_64-w64-mingw32-g++` for `x86_64-w64-mingw32-gcc`,
+/// so that the same compiler name can be used for both languages.
+pub(crate) fn cxx_compiler(compiler: &str) -> PathBuf {
+    let path = Path::new(compiler);
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return path.to_path_buf(),
+    };
+
+    let cxx_name = if name.contains("++") || name.contains("clang-cl") {
+        return path.to_path_buf();
+    } else if let Some(index) = name.rfind("clang") {
+        format!(
+            "{}clang++{}",
+            &name[..index],
+            &name[index + "clang".len()..]
+        )
+    } else if let Some(index) = name.rfind("gcc") {
+        format!("{}g++{}", &name[..index], &name[index + "gcc".len()..])
+    } else if name == "cc" || name.ends_with("-cc") {
+        format!("{}c++", &name[..name.len() - "cc".len()])
+    } else {
+        return path.to_path_buf();
+    };
+
+    path.with_file_name(cxx_name)
+}
+
+/// Return the arguments of a hermetic build with `tool`: the optimization
+/// level, the debug information, the warnings and the target, set like
+/// `cc-rs` does, but without the flags it takes from the environment.
+pub(crate) fn hermetic_args(
+    tool: &cc::Tool,
+    opt_level: &str,
+    debug: bool,
+    host: &str,
+    target: &str,
+) -> Vec<OsString> {
+    let mut args = Vec::new();
+
+    if tool.is_like_msvc() {
+        args.extend(["-nologo", "-MD"].iter().map(|arg| arg.to_string()));
+
+        match opt_level {
+            "1" | "s" | "z" => args.push(String::from("-O1")),
+            "2" | "3" => args.push(String::from("-O2")),
+            _ => (),
+        }
+
+        if debug {
+            args.push(String::from("-Z7"));
+        }
+
+        args.push(String::from("-W4"));
+    } else {
+        if opt_level == "z" && !tool.is_like_clang() {
+            args.push(String::from("-Os"));
+        } else {
+            args.push(format!("-O{}", opt_level));
+        }
+
+        if debug {
+            args.push(String::from("-g"));
+        }
+
+        args.extend(["-Wall", "-Wextra"].iter().map(|arg| arg.to_string()));
+
+        if !target.contains("windows") {
+            args.push(String::from("-fPIC"));
+        }
+
+        if tool.is_like_clang() && host != target {
+            args.push(format!("--target={}", target));
+        }
+    }
+
+    args.into_iter().map(OsString::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hermetic_args() {
+        let host = target_lexicon::HOST.to_string();
+        let tool = cc::Build::new()
+            .cargo_metadata(false)
+            .cargo_warnings(false)
+            .host(&host)
+            .target(&host)
+            .opt_level(0)
+            .debug(false)
+            .compiler("gcc")
+            .try_get_compiler()
+            .unwrap();
+
+        assert_eq!(
+            hermetic_args(&tool, "2", true, &host, &host),
+            ["-O2", "-g", "-Wall", "-Wextra", "-fPIC"]
+        );
+        assert_eq!(hermetic_args(&tool, "z", false, &host, &host)[0], "-Os");
+    }
+
+    #[test]
+    fn test_requirement() {
+        let gcc = Compiler {
+            path: PathBuf::from("gcc"),
+            family: CompilerFamily::Gcc,
+            version: CompilerVersion::parse("13.2.0"),
+        };
+
+        assert!(Requirement::parse("gcc>=13").unwrap().is_met_by(&gcc));
+        assert!(Requirement::parse(">=12.1").unwrap().is_met_by(&gcc));
+        assert!(Requirement::parse("clang>=15, gcc>=12")
+            .unwrap()
+            .is_met_by(&gcc));
+        assert!(!Requirement::parse("gcc>=13.3").unwrap().is_met_by(&gcc));
+        assert!(!Requirement::parse("clang>=15").unwrap().is_met_by(&gcc));
+        assert!(Requirement::parse("clang=15").is_err());
+    }
+
+    #[test]
+    fn test_cxx_compiler() {
+        assert_eq!(cxx_compiler("gcc"), PathBuf::from("g++"));
+        assert_eq!(cxx_compiler("clang-18"), PathBuf::from("clang++-18"));
+        assert_eq!(
+            cxx_compiler("/opt/llvm/bin/clang"),
+            PathBuf::from("/opt/llvm/bin/clang++")
+        );
+        assert_eq!(
+            cxx_compiler("x86_64-w64-mingw32-gcc"),
+            PathBuf::from("x86_64-w64-mingw32-g++")
+        );
+        assert_eq!(cxx_compiler("cc"), PathBuf::from("c++"));
+        assert_eq!(cxx_compiler("clang++"), PathBuf::from("clang++"));
+    }
+}
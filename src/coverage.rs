@@ -0,0 +1,698 @@
+use crate::compiler::CompilerFamily;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    env,
+    ffi::OsStr,
+    fmt::Write as _,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::TempDir;
+
+/// The environment variable naming an lcov file, to which the coverage of
+/// every execution is appended.
+const LCOV: &str = "INLINE_C_RS_LCOV";
+
+/// The hit counts of the lines and branches of a program built with the
+/// `SOURCE_COVERAGE` directive, returned by
+/// [`Execution::coverage`][crate::Execution::coverage].
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    /// The source file, as reported by the coverage tool.
+    source: PathBuf,
+    /// The text of the source lines, from line 1.
+    source_lines: Vec<String>,
+    /// The hit counts of the executable lines.
+    lines: BTreeMap<usize, u64>,
+    /// The hit counts of the outcomes of the branches of each line.
+    branches: BTreeMap<usize, Vec<u64>>,
+}
+
+impl Coverage {
+    /// The number of times that the line was executed, from 1, or `None`
+    /// if the line holds no code.
+    pub fn line(&self, line: usize) -> Option<u64> {
+        self.lines.get(&line).copied()
+    }
+
+    /// The hit counts of the executable lines, by line number.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.lines.iter().map(|(line, hits)| (*line, *hits))
+    }
+
+    /// The number of times that each outcome of the branches of the line
+    /// was taken, e.g. `[0, 1]` for an `if` whose condition was false once.
+    /// It is empty if the line has no branch.
+    pub fn branches(&self, line: usize) -> &[u64] {
+        self.branches.get(&line).map_or(&[], Vec::as_slice)
+    }
+
+    /// The number of the first line containing `text`, e.g. the line of
+    /// `return -1;` in the error handling of the program.
+    pub fn line_containing(&self, text: &str) -> Option<usize> {
+        self.source_lines
+            .iter()
+            .position(|line| line.contains(text))
+            .map(|index| index + 1)
+    }
+
+    /// The number of times that the first line containing `text` was
+    /// executed, or `None` if there is no such line or it holds no code.
+    pub fn hits(&self, text: &str) -> Option<u64> {
+        self.line(self.line_containing(text)?)
+    }
+
+    /// Render the coverage as an lcov record, for tools such as `genhtml`.
+    pub fn lcov(&self) -> String {
+        self.lcov_record(&self.source)
+    }
+
+    fn lcov_record(&self, source: &Path) -> String {
+        let mut record = format!("TN:\nSF:{}\n", source.display());
+
+        for (line, hits) in self.branches.iter() {
+            for (branch, taken) in hits.iter().enumerate() {
+                writeln!(record, "BRDA:{},0,{},{}", line, branch, taken).unwrap();
+            }
+        }
+
+        let branches = self.branches.values().flatten();
+        writeln!(record, "BRF:{}", branches.clone().count()).unwrap();
+        writeln!(
+            record,
+            "BRH:{}",
+            branches.filter(|taken| **taken > 0).count()
+        )
+        .unwrap();
+
+        for (line, hits) in self.lines.iter() {
+            writeln!(record, "DA:{},{}", line, hits).unwrap();
+        }
+
+        writeln!(record, "LF:{}", self.lines.len()).unwrap();
+        writeln!(
+            record,
+            "LH:{}",
+            self.lines.values().filter(|hits| **hits > 0).count()
+        )
+        .unwrap();
+        record.push_str("end_of_record\n");
+
+        record
+    }
+
+    /// Append the coverage to the lcov file named by `INLINE_C_RS_LCOV`,
+    /// if any, to produce a report of a whole test suite. Temporary
+    /// sources are copied next to the lcov file, so that the report
+    /// outlives them.
+    fn append_to_suite(&self, temporary_source: &Path) -> io::Result<()> {
+        let lcov = match env::var_os(LCOV) {
+            Some(lcov) => PathBuf::from(lcov),
+            None => return Ok(()),
+        };
+        let source = if self.source == temporary_source {
+            let contents = fs::read(temporary_source)?;
+            let mut hasher = DefaultHasher::new();
+            contents.hash(&mut hasher);
+
+            let directory = lcov
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join("inline-c-rs-sources");
+            let source = directory.join(format!(
+                "{:016x}.{}",
+                hasher.finish(),
+                temporary_source
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .unwrap_or("c")
+            ));
+
+            fs::create_dir_all(&directory)?;
+            fs::write(&source, contents)?;
+
+            source.canonicalize()?
+        } else {
+            self.source.clone()
+        };
+
+        // The record is appended with a single write, so that the
+        // executions of parallel tests do not interleave.
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(lcov)?
+            .write_all(self.lcov_record(&source).as_bytes())
+    }
+}
+
+/// How a program is instrumented for source coverage.
+#[derive(Clone, Debug)]
+pub(crate) enum Instrumentation {
+    /// `--coverage` of gcc, with the notes file written by the compiler.
+    Gcov {
+        source: PathBuf,
+        notes: PathBuf,
+        gcov: String,
+    },
+    /// The source-based coverage of clang, whose mapping is in the binary.
+    Llvm {
+        source: PathBuf,
+        binary: PathBuf,
+        tools_suffix: String,
+    },
+}
+
+impl Instrumentation {
+    /// The flags of the compiler instrumenting the program.
+    pub(crate) fn flags(family: CompilerFamily) -> Result<&'static [&'static str], String> {
+        match family {
+            CompilerFamily::Gcc => Ok(&["--coverage"]),
+            CompilerFamily::Clang => Ok(&["-fprofile-instr-generate", "-fcoverage-mapping"]),
+            CompilerFamily::Msvc => Err(String::from(
+                "`SOURCE_COVERAGE` requires gcc or clang, not MSVC",
+            )),
+        }
+    }
+
+    /// Describe the instrumentation of the program compiled from `source`
+    /// to `binary` by `compiler`, after the compilation.
+    pub(crate) fn new(
+        family: CompilerFamily,
+        compiler: &Path,
+        source: &Path,
+        binary: &Path,
+    ) -> io::Result<Self> {
+        let compiler_name = compiler
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default();
+
+        match family {
+            CompilerFamily::Gcc => {
+                // The notes are named after the output and the source since
+                // gcc 11, e.g. `out-source.gcno`, and after the source before.
+                let source_stem = source.file_stem().unwrap_or_default().to_string_lossy();
+                let mut candidates = vec![PathBuf::from(format!(
+                    "{}-{}.gcno",
+                    binary.with_extension("").display(),
+                    source_stem
+                ))];
+                candidates.extend(
+                    [binary.parent(), env::current_dir().ok().as_deref()]
+                        .iter()
+                        .flatten()
+                        .map(|directory| directory.join(format!("{}.gcno", source_stem))),
+                );
+                let notes = candidates
+                    .into_iter()
+                    .find(|notes| notes.is_file())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "The compiler did not write the coverage notes",
+                        )
+                    })?;
+
+                Ok(Self::Gcov {
+                    source: source.to_path_buf(),
+                    notes,
+                    gcov: gcov(compiler_name),
+                })
+            }
+            _ => Ok(Self::Llvm {
+                source: source.to_path_buf(),
+                binary: binary.to_path_buf(),
+                tools_suffix: llvm_tools_suffix(compiler_name),
+            }),
+        }
+    }
+
+    /// The first tool reading the profiles of the programs built by
+    /// `compiler` which is not installed, if any.
+    pub(crate) fn missing_tool(family: CompilerFamily, compiler: &Path) -> Option<String> {
+        let compiler_name = compiler
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default();
+        let tools = match family {
+            CompilerFamily::Gcc => vec![gcov(compiler_name)],
+            _ => {
+                let suffix = llvm_tools_suffix(compiler_name);
+
+                vec![
+                    format!("llvm-profdata{}", suffix),
+                    format!("llvm-cov{}", suffix),
+                ]
+            }
+        };
+
+        // Only the spawn matters, since `llvm-profdata --version` fails.
+        tools.into_iter().find(|tool| {
+            Command::new(tool)
+                .arg("--version")
+                .output()
+                .is_err_and(|error| error.kind() == io::ErrorKind::NotFound)
+        })
+    }
+
+    /// The files written by the compilation besides the binary.
+    pub(crate) fn files(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Gcov { notes, .. } => vec![notes.clone()],
+            Self::Llvm { .. } => Vec::new(),
+        }
+    }
+
+    /// The environment variables set by [`Instrumentation::prepare`].
+    pub(crate) fn variables(&self) -> &'static [&'static str] {
+        match self {
+            Self::Gcov { .. } => &["GCOV_PREFIX", "GCOV_PREFIX_STRIP"],
+            Self::Llvm { .. } => &["LLVM_PROFILE_FILE"],
+        }
+    }
+
+    /// Make the program write its profile in a new directory, which is
+    /// returned.
+    pub(crate) fn prepare(&self, command: &mut Command) -> io::Result<TempDir> {
+        let profile = tempfile::Builder::new().prefix("inline-c-rs-").tempdir()?;
+
+        match self {
+            // The data file is written at its absolute path, under the
+            // prefix.
+            Self::Gcov { .. } => {
+                command
+                    .env("GCOV_PREFIX", profile.path())
+                    .env("GCOV_PREFIX_STRIP", "0");
+            }
+            Self::Llvm { .. } => {
+                command.env("LLVM_PROFILE_FILE", profile.path().join("%p.profraw"));
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Read the profile written by the program, and append it to the lcov
+    /// file of the test suite, if any.
+    pub(crate) fn collect(&self, profile: &Path) -> io::Result<Coverage> {
+        let (source, coverage) = match self {
+            Self::Gcov {
+                source,
+                notes,
+                gcov,
+            } => {
+                // The data file is missing if the program did not exit
+                // normally, and the counts are then 0.
+                let data_directory = files(profile)?
+                    .into_iter()
+                    .find(|file| file.extension() == Some(OsStr::new("gcda")))
+                    .and_then(|data| data.parent().map(Path::to_path_buf))
+                    .unwrap_or_else(|| profile.to_path_buf());
+                let notes_copy = data_directory.join(notes.file_name().unwrap_or_default());
+
+                fs::copy(notes, &notes_copy)?;
+
+                let report = tool_output(
+                    Command::new(gcov)
+                        .args(["-b", "-c", "-t", "-o"])
+                        .arg(&notes_copy)
+                        .arg(source)
+                        .current_dir(profile),
+                )?;
+
+                (source, parse_gcov(&report))
+            }
+            Self::Llvm {
+                source,
+                binary,
+                tools_suffix,
+            } => {
+                let raw_profiles = files(profile)?
+                    .into_iter()
+                    .filter(|file| file.extension() == Some(OsStr::new("profraw")))
+                    .collect::<Vec<_>>();
+
+                if raw_profiles.is_empty() {
+                    return Err(io::Error::other(
+                        "The program did not write a coverage profile, it must exit normally",
+                    ));
+                }
+
+                let merged = profile.join("merged.profdata");
+
+                tool_output(
+                    Command::new(format!("llvm-profdata{}", tools_suffix))
+                        .args(["merge", "-sparse", "-o"])
+                        .arg(&merged)
+                        .args(&raw_profiles),
+                )?;
+
+                let report = tool_output(
+                    Command::new(format!("llvm-cov{}", tools_suffix))
+                        .args(["export", "-format=lcov", "-instr-profile"])
+                        .arg(&merged)
+                        .arg(binary),
+                )?;
+
+                (source, parse_lcov(&report))
+            }
+        };
+
+        let mut coverage = coverage;
+
+        if coverage.source.as_os_str().is_empty() {
+            coverage.source = source.clone();
+        }
+
+        coverage.source_lines = fs::read_to_string(&coverage.source)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect();
+        coverage.append_to_suite(source)?;
+
+        Ok(coverage)
+    }
+}
+
+/// The `gcov` matching a gcc, e.g. `gcov-12` for `gcc-12`, and
+/// `x86_64-linux-gnu-gcov` for `x86_64-linux-gnu-gcc`.
+fn gcov(compiler_name: &str) -> String {
+    match compiler_name.rfind("gcc") {
+        Some(index) => format!(
+            "{}gcov{}",
+            &compiler_name[..index],
+            &compiler_name[index + 3..]
+        ),
+        None => String::from("gcov"),
+    }
+}
+
+/// The suffix of the LLVM tools matching a clang, e.g. `-18` for
+/// `clang-18`.
+fn llvm_tools_suffix(compiler_name: &str) -> String {
+    compiler_name
+        .rfind("clang")
+        .map(|index| compiler_name[index + 5..].to_string())
+        .filter(|suffix| suffix.starts_with('-'))
+        .unwrap_or_default()
+}
+
+/// The files of a directory and its subdirectories.
+fn files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                directories.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Run a coverage tool, and return its output.
+fn tool_output(command: &mut Command) -> io::Result<String> {
+    let output = command.output().map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!(
+                "Failed to run `{}`: {}",
+                command.get_program().to_string_lossy(),
+                error
+            ),
+        )
+    })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`{}` failed: {}",
+            command.get_program().to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse the report of `gcov --branch-counts --stdout`, e.g.:
+///
+/// ```text
+///         -:    0:Source:program.c
+///         1:    3:  if (argc > 1) {
+/// branch  0 taken 0 (fallthrough)
+/// branch  1 taken 1
+///     #####:    4:    printf("args\n");
+/// ```
+///
+/// Only the first source, the compiled one, is read.
+fn parse_gcov(report: &str) -> Coverage {
+    let mut coverage = Coverage::default();
+    let mut current_line = 0;
+
+    for line in report.lines() {
+        if let Some(branch) = line.strip_prefix("branch ") {
+            let taken = branch
+                .split_whitespace()
+                .nth(2)
+                .and_then(|taken| taken.parse().ok())
+                .unwrap_or(0);
+
+            coverage
+                .branches
+                .entry(current_line)
+                .or_default()
+                .push(taken);
+            continue;
+        }
+
+        let mut fields = line.splitn(3, ':');
+        let (hits, number, text) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(hits), Some(number), Some(text)) => (hits.trim(), number.trim(), text),
+            _ => continue,
+        };
+        let number = match number.parse::<usize>() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+
+        if number == 0 {
+            if let Some(source) = text.strip_prefix("Source:") {
+                // A second source, e.g. a header, ends the first one.
+                if !coverage.source.as_os_str().is_empty() {
+                    break;
+                }
+
+                coverage.source = PathBuf::from(source);
+            }
+
+            continue;
+        }
+
+        current_line = number;
+
+        // `-` is a line without code, `#####` and `=====` a line that was
+        // not executed, and `*` marks a line with a block not executed.
+        match hits {
+            "-" => (),
+            "#####" | "=====" => {
+                coverage.lines.insert(number, 0);
+            }
+            hits => {
+                if let Ok(hits) = hits.trim_end_matches('*').parse() {
+                    coverage.lines.insert(number, hits);
+                }
+            }
+        }
+    }
+
+    coverage
+}
+
+/// Parse the first record of an lcov report, e.g.:
+///
+/// ```text
+/// SF:program.c
+/// DA:3,1
+/// BRDA:3,0,0,0
+/// BRDA:3,0,1,1
+/// end_of_record
+/// ```
+fn parse_lcov(report: &str) -> Coverage {
+    let mut coverage = Coverage::default();
+
+    for line in report.lines() {
+        let (kind, value) = match line.split_once(':') {
+            Some(field) => field,
+            None if line == "end_of_record" => break,
+            None => continue,
+        };
+        let fields = value.split(',').collect::<Vec<_>>();
+
+        match (kind, fields.as_slice()) {
+            ("SF", _) => coverage.source = PathBuf::from(value),
+            ("DA", [line, hits, ..]) => {
+                if let (Ok(line), Ok(hits)) = (line.parse(), hits.parse()) {
+                    coverage.lines.insert(line, hits);
+                }
+            }
+            ("BRDA", [line, _, _, taken]) => {
+                if let Ok(line) = line.parse() {
+                    // `-` is a branch whose block was not executed.
+                    coverage
+                        .branches
+                        .entry(line)
+                        .or_default()
+                        .push(taken.parse().unwrap_or(0));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Language, Skipped};
+
+    #[test]
+    fn test_parse_gcov() {
+        let coverage = parse_gcov(
+            "        -:    0:Source:/tmp/program.c\n\
+             \x20       -:    0:Runs:1\n\
+             function main called 1 returned 100% blocks executed 86%\n\
+             \x20       1:    2:int main(int argc, char** argv) {\n\
+             \x20       1:    3:  if (argc > 1) {\n\
+             branch  0 taken 0 (fallthrough)\n\
+             branch  1 taken 1\n\
+             \x20   #####:    4:    printf(\"args\\n\");\n\
+             call    0 never executed\n\
+             \x20       -:    5:  }\n\
+             \x20      4*:    6:  for (int i = 0; i < 3; i++) {}\n\
+             branch  0 never executed\n\
+             \x20       -:    0:Source:/usr/include/stdio.h\n\
+             \x20       1:    1:header\n",
+        );
+
+        assert_eq!(coverage.source, Path::new("/tmp/program.c"));
+        assert_eq!(
+            coverage.lines().collect::<Vec<_>>(),
+            [(2, 1), (3, 1), (4, 0), (6, 4)]
+        );
+        assert_eq!(coverage.branches(3), [0, 1]);
+        assert_eq!(coverage.branches(6), [0]);
+        assert!(coverage.branches(4).is_empty());
+    }
+
+    #[test]
+    fn test_parse_lcov() {
+        let coverage = parse_lcov(
+            "SF:/tmp/program.c\n\
+             DA:2,1\n\
+             DA:4,0\n\
+             BRDA:3,0,0,0\n\
+             BRDA:3,0,1,1\n\
+             BRDA:6,1,0,-\n\
+             end_of_record\n\
+             SF:/usr/include/stdio.h\n\
+             DA:1,1\n\
+             end_of_record\n",
+        );
+
+        assert_eq!(coverage.lines().collect::<Vec<_>>(), [(2, 1), (4, 0)]);
+        assert_eq!(coverage.branches(3), [0, 1]);
+        assert_eq!(coverage.branches(6), [0]);
+        assert_eq!(
+            coverage.lcov(),
+            "TN:\nSF:/tmp/program.c\nBRDA:3,0,0,0\nBRDA:3,0,1,1\nBRDA:6,0,0,0\nBRF:3\nBRH:1\nDA:2,1\nDA:4,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_missing_tool() {
+        let directory = tempfile::tempdir().unwrap();
+        let compiler = directory.path().join("gcc-99");
+        let gcc = Command::new("sh")
+            .args(["-c", "command -v gcc"])
+            .output()
+            .unwrap();
+
+        std::os::unix::fs::symlink(String::from_utf8_lossy(&gcc.stdout).trim(), &compiler).unwrap();
+
+        let error = run(
+            Language::C,
+            &format!(
+                "#inline_c_rs SOURCE_COVERAGE\n#inline_c_rs CC: \"{}\"\nint main() {{ return 0; }}",
+                compiler.display()
+            ),
+        )
+        .err()
+        .expect("`gcov-99` is not installed");
+
+        assert!(error.is::<Skipped>(), "{}", error);
+        assert!(error.to_string().contains("`gcov-99`"));
+    }
+
+    #[test]
+    fn test_source_coverage() {
+        // The test is skipped when `gcov` is not installed.
+        crate::skippable(|| {
+            let program = r#"
+                #inline_c_rs SOURCE_COVERAGE
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main() {
+                    const char* input = getenv("INPUT");
+
+                    if (input == NULL) {
+                        return 1; // error handling
+                    }
+
+                    printf("%s\n", input);
+
+                    return 0;
+                }
+            "#;
+            let mut assert = run(Language::C, program).unwrap_or_else(|error| crate::fail(error));
+
+            let execution = assert.env("INPUT", "Hello").execute().unwrap();
+            let coverage = execution.coverage().unwrap();
+
+            assert!(execution.success());
+            assert_eq!(coverage.hits("printf"), Some(1));
+            assert_eq!(coverage.hits("// error handling"), Some(0));
+            assert_eq!(coverage.hits("#include"), None);
+            assert_eq!(
+                coverage
+                    .branches(coverage.line_containing("if (input").unwrap())
+                    .iter()
+                    .sum::<u64>(),
+                1
+            );
+            assert!(coverage.lcov().contains("DA:"));
+
+            // Every execution has its own counts.
+            let execution = assert.env_remove("INPUT").execute().unwrap();
+            let coverage = execution.coverage().unwrap();
+
+            assert_eq!(coverage.hits("printf"), Some(0));
+            assert_eq!(coverage.hits("// error handling"), Some(1));
+        });
+    }
+}
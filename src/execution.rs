@@ -0,0 +1,606 @@
+use crate::{
+    coverage::Coverage,
+    limit::{self, Resource},
+};
+use assert_cmd::assert::OutputAssertExt;
+use std::{
+    fmt,
+    fmt::Display,
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// How an executed program ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program exited with the given code.
+    Exited(i32),
+    /// The program was killed by the given signal.
+    Signaled(i32),
+    /// The program was killed for exceeding the limit of the given
+    /// resource, which is only known for the CPU time and the file size.
+    /// A program exceeding its address space or open files limit sees its
+    /// allocations or opens fail, and ends as it handles them.
+    LimitExceeded(Resource),
+    /// The program exceeded the timeout of a
+    /// [`Forkserver`][crate::Forkserver], and was killed.
+    TimedOut,
+}
+
+impl Outcome {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub(crate) fn new(
+        status: ExitStatus,
+        limits: &[(Resource, u64)],
+        cpu_time: Option<Duration>,
+    ) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            if let Some(signal) = status.signal() {
+                return match limit::exceeded(limits, signal, cpu_time) {
+                    Some(resource) => Self::LimitExceeded(resource),
+                    None => Self::Signaled(signal),
+                };
+            }
+        }
+
+        Self::Exited(status.code().unwrap_or(-1))
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exited(code) => write!(f, "exited with code {}", code),
+            Self::Signaled(signal) => write!(f, "killed by signal {}", signal),
+            Self::LimitExceeded(resource) => write!(f, "exceeded `{}`", resource),
+            Self::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// The result of an execution of the compiled program, returned by
+/// [`Assert::execute`][crate::Assert::execute].
+#[derive(Clone, Debug)]
+pub struct Execution {
+    outcome: Outcome,
+    status: ExitStatus,
+    stdout: Capture,
+    stderr: Capture,
+    wall_time: Duration,
+    usage: Option<Usage>,
+    working_directory: Arc<TempDir>,
+    fixtures: Arc<[(PathBuf, Vec<u8>)]>,
+    coverage: Option<Coverage>,
+}
+
+impl Execution {
+    /// Run the command in a new working directory holding the `fixtures`,
+    /// and capture at most `output_limit` bytes of each of its outputs.
+    /// The `limits` are those applied to the command.
+    pub(crate) fn run(
+        command: &mut Command,
+        output_limit: usize,
+        limits: &[(Resource, u64)],
+        fixtures: &[(PathBuf, Vec<u8>)],
+    ) -> io::Result<Self> {
+        Self::start(command, output_limit, limits, fixtures)?.wait()
+    }
+
+    /// Like [`Execution::run`], but return once the program is spawned.
+    pub(crate) fn start(
+        command: &mut Command,
+        output_limit: usize,
+        limits: &[(Resource, u64)],
+        fixtures: &[(PathBuf, Vec<u8>)],
+    ) -> io::Result<Running> {
+        let working_directory = working_directory(fixtures)?;
+        let start = Instant::now();
+        let mut child = command
+            .current_dir(working_directory.path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        Ok(Running {
+            stdout: capture(child.stdout.take(), output_limit),
+            stderr: capture(child.stderr.take(), output_limit),
+            child,
+            start,
+            limits: limits.to_vec(),
+            working_directory,
+            fixtures: fixtures.into(),
+        })
+    }
+
+    pub(crate) fn with_coverage(mut self, coverage: Coverage) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+
+    /// How the program ended.
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// The exit status of the program.
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Whether the program exited with a zero code.
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// The standard output of the program, up to the output limit.
+    pub fn stdout(&self) -> &[u8] {
+        &self.stdout.bytes
+    }
+
+    /// The standard error of the program, up to the output limit.
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr.bytes
+    }
+
+    /// Whether the standard output exceeded the output limit, and was cut.
+    pub fn stdout_truncated(&self) -> bool {
+        self.stdout.truncated
+    }
+
+    /// Whether the standard error exceeded the output limit, and was cut.
+    pub fn stderr_truncated(&self) -> bool {
+        self.stderr.truncated
+    }
+
+    /// The time elapsed between the start of the program and its end.
+    pub fn wall_time(&self) -> Duration {
+        self.wall_time
+    }
+
+    /// The CPU time spent by the program in user mode. It is only
+    /// available on Unix.
+    pub fn user_time(&self) -> Option<Duration> {
+        self.usage.map(|usage| usage.user_time)
+    }
+
+    /// The CPU time spent by the kernel on behalf of the program. It is
+    /// only available on Unix.
+    pub fn system_time(&self) -> Option<Duration> {
+        self.usage.map(|usage| usage.system_time)
+    }
+
+    /// The peak resident set size of the program, in bytes. It is only
+    /// available on Unix.
+    pub fn peak_rss(&self) -> Option<u64> {
+        self.usage.map(|usage| usage.peak_rss)
+    }
+
+    /// The lines and branches executed by the program, if it was built
+    /// with the `SOURCE_COVERAGE` directive.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// The working directory of the program, which is created for this
+    /// execution, and removed when the execution is dropped.
+    pub fn working_directory(&self) -> &Path {
+        self.working_directory.path()
+    }
+
+    /// Read a file of the working directory, e.g. a file written by the
+    /// program.
+    pub fn file<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<u8>> {
+        fs::read(self.working_directory().join(relative(path.as_ref())?))
+    }
+
+    /// The files of the working directory that the program created or
+    /// changed, relative to it and sorted, e.g. `["data/output.txt"]`.
+    /// The fixtures that the program left unchanged are omitted, and the
+    /// ones that it removed are listed by [`Execution::deleted_files`].
+    pub fn changed_files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let mut directories = vec![self.working_directory().to_path_buf()];
+
+        while let Some(directory) = directories.pop() {
+            for entry in fs::read_dir(directory)? {
+                let path = entry?.path();
+
+                if path.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+
+                let relative_path = path
+                    .strip_prefix(self.working_directory())
+                    .expect("The file should be in the working directory")
+                    .to_path_buf();
+                let fixture = self
+                    .fixtures
+                    .iter()
+                    .rev()
+                    .find(|(fixture, _)| *fixture == relative_path);
+
+                match fixture {
+                    Some((_, contents)) if fs::read(&path)? == *contents => (),
+                    _ => files.push(relative_path),
+                }
+            }
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// The fixtures that the program removed from the working directory,
+    /// relative to it and sorted, e.g. `["input.txt"]`.
+    pub fn deleted_files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .fixtures
+            .iter()
+            .map(|(path, _)| path.clone())
+            .filter(|path| !self.working_directory().join(path).is_file())
+            .collect::<Vec<_>>();
+
+        files.sort();
+        files.dedup();
+
+        files
+    }
+
+    /// Assert on the captured output, with [`assert_cmd`].
+    pub fn assert(&self) -> assert_cmd::assert::Assert {
+        Output {
+            status: self.status,
+            stdout: self.stdout.bytes.clone(),
+            stderr: self.stderr.bytes.clone(),
+        }
+        .assert()
+    }
+}
+
+/// Create a new working directory holding the `fixtures`.
+pub(crate) fn working_directory(fixtures: &[(PathBuf, Vec<u8>)]) -> io::Result<TempDir> {
+    let working_directory = tempfile::Builder::new().prefix("inline-c-rs-").tempdir()?;
+
+    for (path, contents) in fixtures {
+        let path = working_directory.path().join(relative(path)?);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)?;
+    }
+
+    Ok(working_directory)
+}
+
+/// Check that a path stays in the working directory, e.g. `data/input.txt`
+/// but not `/etc/passwd` or `../input.txt`.
+fn relative(path: &Path) -> io::Result<&Path> {
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "`{}` is not a relative path in the working directory",
+                path.display()
+            ),
+        ))
+    }
+}
+
+impl From<Execution> for Output {
+    fn from(execution: Execution) -> Self {
+        Output {
+            status: execution.status,
+            stdout: execution.stdout.bytes,
+            stderr: execution.stderr.bytes,
+        }
+    }
+}
+
+/// An output of the program.
+#[derive(Clone, Debug, Default)]
+struct Capture {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+/// Read an output of the program in a thread, keeping its first `limit`
+/// bytes. The rest is read and discarded, so that the program does not
+/// block on a full pipe.
+fn capture<R: Read + Send + 'static>(
+    reader: Option<R>,
+    limit: usize,
+) -> thread::JoinHandle<io::Result<Capture>> {
+    thread::spawn(move || {
+        let mut capture = Capture::default();
+        let mut reader = match reader {
+            Some(reader) => reader,
+            None => return Ok(capture),
+        };
+        let mut buffer = [0; 8192];
+
+        loop {
+            let length = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(capture),
+                Ok(length) => length,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            let kept = length.min(limit - capture.bytes.len());
+
+            capture.bytes.extend_from_slice(&buffer[..kept]);
+            capture.truncated |= kept < length;
+        }
+    })
+}
+
+fn join(capture: thread::JoinHandle<io::Result<Capture>>) -> io::Result<Capture> {
+    capture
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("Failed to capture the output")))
+}
+
+/// A spawned program, whose execution is returned by [`Running::wait`].
+pub(crate) struct Running {
+    child: Child,
+    stdout: thread::JoinHandle<io::Result<Capture>>,
+    stderr: thread::JoinHandle<io::Result<Capture>>,
+    start: Instant,
+    limits: Vec<(Resource, u64)>,
+    working_directory: TempDir,
+    fixtures: Arc<[(PathBuf, Vec<u8>)]>,
+}
+
+impl Running {
+    /// Wait for the program to end, and for its outputs.
+    pub(crate) fn wait(mut self) -> io::Result<Execution> {
+        let (status, usage) = wait(&mut self.child)?;
+        let wall_time = self.start.elapsed();
+
+        Ok(Execution {
+            outcome: Outcome::new(
+                status,
+                &self.limits,
+                usage.map(|usage| usage.user_time + usage.system_time),
+            ),
+            status,
+            stdout: join(self.stdout)?,
+            stderr: join(self.stderr)?,
+            wall_time,
+            usage,
+            working_directory: Arc::new(self.working_directory),
+            fixtures: self.fixtures,
+            coverage: None,
+        })
+    }
+}
+
+/// The resources used by the program.
+#[derive(Clone, Copy, Debug)]
+struct Usage {
+    user_time: Duration,
+    system_time: Duration,
+    peak_rss: u64,
+}
+
+/// Wait for the program, and collect the resources it used with `wait4`.
+#[cfg(unix)]
+fn wait(child: &mut Child) -> io::Result<(ExitStatus, Option<Usage>)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+
+    loop {
+        // SAFETY: the child has not been waited for yet, and `status` and
+        // `usage` are valid for writes.
+        let pid = unsafe {
+            libc::wait4(
+                child.id() as libc::pid_t,
+                &mut status,
+                0,
+                usage.as_mut_ptr(),
+            )
+        };
+
+        if pid != -1 {
+            break;
+        }
+
+        let error = io::Error::last_os_error();
+
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    // SAFETY: `wait4` succeeded, so it filled `usage`.
+    let usage = unsafe { usage.assume_init() };
+    let duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // `ru_maxrss` is in kilobytes, except on Apple platforms.
+    let peak_rss = if cfg!(any(target_os = "macos", target_os = "ios")) {
+        usage.ru_maxrss as u64
+    } else {
+        usage.ru_maxrss as u64 * 1024
+    };
+
+    Ok((
+        ExitStatus::from_raw(status),
+        Some(Usage {
+            user_time: duration(usage.ru_utime),
+            system_time: duration(usage.ru_stime),
+            peak_rss,
+        }),
+    ))
+}
+
+#[cfg(not(unix))]
+fn wait(child: &mut Child) -> io::Result<(ExitStatus, Option<Usage>)> {
+    Ok((child.wait()?, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{run, Language, Outcome};
+    use std::path::Path;
+
+    #[test]
+    fn test_execute() {
+        let execution = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+                #include <stdlib.h>
+                #include <string.h>
+
+                int main() {
+                    char* buffer = malloc(64 << 20);
+                    memset(buffer, 1, 64 << 20);
+
+                    printf("Hello, World!\n");
+                    fprintf(stderr, "%d\n", buffer[42]);
+
+                    return 3;
+                }
+            "#,
+        )
+        .unwrap()
+        .execute()
+        .unwrap();
+
+        assert_eq!(execution.outcome(), Outcome::Exited(3));
+        assert_eq!(execution.stdout(), b"Hello, World!\n");
+        assert_eq!(execution.stderr(), b"1\n");
+        assert!(!execution.stdout_truncated());
+        assert!(execution.wall_time() > std::time::Duration::ZERO);
+
+        if cfg!(unix) {
+            assert!(execution.peak_rss().unwrap() >= 64 << 20);
+            assert!(execution.user_time().is_some());
+            assert!(execution.system_time().is_some());
+        }
+
+        execution.assert().failure().code(3);
+    }
+
+    #[test]
+    fn test_execute_with_output_limit() {
+        let execution = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    for (int i = 0; i < 100000; i++) {
+                        printf("%d\n", i);
+                    }
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .output_limit(8)
+        .execute()
+        .unwrap();
+
+        assert!(execution.success());
+        assert_eq!(execution.stdout(), b"0\n1\n2\n3\n");
+        assert!(execution.stdout_truncated());
+        assert!(!execution.stderr_truncated());
+    }
+
+    #[test]
+    fn test_execute_with_fixtures() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs FIXTURE "input.txt": "Hello,\nWorld!\n"
+                #include <stdio.h>
+
+                int main() {
+                    char line[64];
+                    FILE* input = fopen("input.txt", "r");
+                    FILE* output = fopen("output.txt", "w");
+                    FILE* data = fopen("data/state", "a");
+
+                    if (input == NULL || output == NULL || data == NULL || remove("data/obsolete") != 0) {
+                        return 1;
+                    }
+
+                    while (fgets(line, sizeof(line), input) != NULL) {
+                        fprintf(output, "> %s", line);
+                    }
+
+                    fputc('!', data);
+
+                    return fclose(output) != 0 || fclose(data) != 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        let first = assert
+            .fixture("data/state", b"\xff")
+            .fixture("data/unchanged", "")
+            .fixture("data/obsolete", "")
+            .execute()
+            .unwrap();
+        let second = assert.execute().unwrap();
+
+        assert!(first.success());
+        assert_ne!(first.working_directory(), second.working_directory());
+        assert_eq!(
+            first.changed_files().unwrap(),
+            [Path::new("data/state"), Path::new("output.txt")]
+        );
+        assert_eq!(first.deleted_files(), [Path::new("data/obsolete")]);
+        assert_eq!(first.file("output.txt").unwrap(), b"> Hello,\n> World!\n");
+        assert_eq!(second.file("data/state").unwrap(), b"\xff!");
+        assert!(first.file("../output.txt").is_err());
+        assert!(assert.fixture("/etc/passwd", "").execute().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_signaled() {
+        let execution = run(
+            Language::C,
+            r#"
+                #include <signal.h>
+
+                int main() {
+                    raise(SIGABRT);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .execute()
+        .unwrap();
+
+        assert_eq!(execution.outcome(), Outcome::Signaled(libc::SIGABRT));
+        assert!(!execution.success());
+    }
+}
@@ -0,0 +1,178 @@
+/// Split flags such as `-DGREETING='"Hello, World!"' -I "my headers"`
+/// into arguments, following the quoting rules of a POSIX shell.
+pub(crate) fn split(name: &str, flags: &str) -> Result<Vec<String>, String> {
+    shlex::split(flags).ok_or_else(|| format!("Invalid quoting in `{}`: `{}`", name, flags))
+}
+
+/// Flags of a GCC-compatible driver that also take the next argument.
+const DRIVER_FLAGS_WITH_VALUE: &[&str] = &["-L", "-l", "-Xlinker", "-z", "-u", "-T"];
+
+/// Flags that a GCC-compatible driver understands itself, and which must
+/// not be passed to the linker with `-Wl,`.
+const DRIVER_FLAGS: &[&str] = &[
+    "-s",
+    "-static",
+    "-static-libgcc",
+    "-static-libstdc++",
+    "-shared",
+    "-pthread",
+    "-rdynamic",
+    "-pie",
+    "-no-pie",
+    "-nostdlib",
+    "-nostartfiles",
+    "-nodefaultlibs",
+    "-m32",
+    "-m64",
+    "-mx32",
+];
+
+/// Prefixes of the flags that a GCC-compatible driver understands itself,
+/// e.g. `-Lvendor/lib` or `-fuse-ld=lld`. Only the `-m` forms selecting the
+/// target are listed, as the linker has its own `-m <emulation>`.
+const DRIVER_FLAG_PREFIXES: &[&str] = &[
+    "-L",
+    "-l",
+    "-f",
+    "-march=",
+    "-mcpu=",
+    "-mtune=",
+    "-mabi=",
+    "-Wl,",
+    "-T",
+    "--sysroot=",
+];
+
+/// Encode the `LDFLAGS` for the compiler driver.
+///
+/// For a GCC-compatible driver, flags that the driver understands, such as
+/// `-L`, `-l` or `-fuse-ld=lld`, are kept as-is, and the others, such as
+/// `--gc-sections`, are passed to the linker with `-Wl,`. For MSVC, the
+/// arguments are meant to follow `/link`: `-L` becomes `/LIBPATH:`, `-l`
+/// becomes a `.lib` file, and `-Wl,` is unwrapped.
+pub(crate) fn linker_arguments(flags: &[String], msvc: bool) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut flags = flags.iter();
+
+    if msvc {
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "-L" => arguments.extend(flags.next().map(|path| format!("/LIBPATH:{}", path))),
+                "-l" => arguments.extend(flags.next().map(|name| library(name, true))),
+                "-Xlinker" => arguments.extend(flags.next().cloned()),
+                flag => {
+                    if let Some(path) = flag.strip_prefix("-L") {
+                        arguments.push(format!("/LIBPATH:{}", path));
+                    } else if let Some(name) = flag.strip_prefix("-l") {
+                        arguments.push(library(name, true));
+                    } else if let Some(linker_flags) = flag.strip_prefix("-Wl,") {
+                        arguments.extend(linker_flags.split(',').map(String::from));
+                    } else {
+                        arguments.push(flag.to_string());
+                    }
+                }
+            }
+        }
+    } else {
+        // A value following a flag passed with `-Wl,`, e.g. the path of
+        // `-rpath`, must be passed with `-Wl,` too.
+        let mut previous_is_wrapped = false;
+
+        while let Some(flag) = flags.next() {
+            if DRIVER_FLAGS_WITH_VALUE.contains(&flag.as_str()) {
+                arguments.push(flag.clone());
+                arguments.extend(flags.next().cloned());
+                previous_is_wrapped = false;
+            } else if DRIVER_FLAGS.contains(&flag.as_str())
+                || DRIVER_FLAG_PREFIXES
+                    .iter()
+                    .any(|prefix| flag.starts_with(prefix))
+                || (!flag.starts_with('-') && !previous_is_wrapped)
+            {
+                arguments.push(flag.clone());
+                previous_is_wrapped = false;
+            } else {
+                arguments.push(format!("-Wl,{}", flag));
+                previous_is_wrapped = flag.starts_with('-');
+            }
+        }
+    }
+
+    arguments
+}
+
+/// Return the argument linking the library `name`, e.g. `-lm` or `m.lib`.
+pub(crate) fn library(name: &str, msvc: bool) -> String {
+    if msvc {
+        if name.ends_with(".lib") {
+            name.to_string()
+        } else {
+            format!("{}.lib", name)
+        }
+    } else {
+        format!("-l{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(arguments: &[&str]) -> Vec<String> {
+        arguments.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("CFLAGS", r#"-DGREETING='"Hello, World!"' -I "my headers""#).unwrap(),
+            strings(&["-DGREETING=\"Hello, World!\"", "-I", "my headers"])
+        );
+        assert!(split("CFLAGS", "-DGREETING='Hello").is_err());
+    }
+
+    #[test]
+    fn test_linker_arguments() {
+        let flags = strings(&[
+            "-L",
+            "vendor/lib",
+            "-lz",
+            "-fuse-ld=lld",
+            "--gc-sections",
+            "-rpath",
+            "/opt/lib",
+            "-Wl,--as-needed",
+            "-m64",
+            "-march=native",
+            "-m",
+            "elf_x86_64",
+            "foo.o",
+        ]);
+
+        assert_eq!(
+            linker_arguments(&flags, false),
+            strings(&[
+                "-L",
+                "vendor/lib",
+                "-lz",
+                "-fuse-ld=lld",
+                "-Wl,--gc-sections",
+                "-Wl,-rpath",
+                "-Wl,/opt/lib",
+                "-Wl,--as-needed",
+                "-m64",
+                "-march=native",
+                "-Wl,-m",
+                "-Wl,elf_x86_64",
+                "foo.o",
+            ])
+        );
+        assert_eq!(
+            linker_arguments(
+                &strings(&["-Lvendor/lib", "-lz", "-Wl,/DEBUG,/OPT:REF"]),
+                true
+            ),
+            strings(&["/LIBPATH:vendor/lib", "z.lib", "/DEBUG", "/OPT:REF"])
+        );
+    }
+}
@@ -0,0 +1,66 @@
+/*
+ * The forkserver runtime linked by the `FORKSERVER` directive. It speaks
+ * a minimal protocol modelled on the classic AFL forkserver: before
+ * `main`, it says hello on the status descriptor, then, for each request
+ * read on the control descriptor, it forks a child running `main`, and
+ * writes the child pid and its wait status on the status descriptor.
+ *
+ * It only talks to the `Forkserver` driver of this crate. The hello is the
+ * legacy one, without the versioned handshake of AFL++ 4.21 and of the
+ * `ForkserverExecutor` of LibAFL, and there is no shared memory coverage
+ * map (`__AFL_SHM_ID`).
+ *
+ * Without a driver, the hello fails, and the program runs normally.
+ */
+
+#ifndef _POSIX_C_SOURCE
+#define _POSIX_C_SOURCE 200809L
+#endif
+
+#include <sys/types.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define FORKSRV_FD 198
+
+__attribute__((constructor)) static void inline_c_rs_forkserver(void) {
+    unsigned char hello[4] = {0, 0, 0, 0};
+
+    if (write(FORKSRV_FD + 1, hello, 4) != 4) {
+        return;
+    }
+
+    for (;;) {
+        unsigned char was_killed[4];
+        pid_t child;
+        int status = 0;
+
+        /*
+         * The request tells whether the driver killed the previous child.
+         * AFL needs it to reap a child stopped in persistent mode, but here
+         * every child runs to its end, and has been reaped by `waitpid`
+         * before the next request, so it is ignored.
+         */
+        if (read(FORKSRV_FD, was_killed, 4) != 4) {
+            _exit(1);
+        }
+
+        child = fork();
+
+        if (child < 0) {
+            _exit(1);
+        }
+
+        if (child == 0) {
+            close(FORKSRV_FD);
+            close(FORKSRV_FD + 1);
+
+            return;
+        }
+
+        if (write(FORKSRV_FD + 1, &child, 4) != 4 || waitpid(child, &status, 0) < 0 ||
+            write(FORKSRV_FD + 1, &status, 4) != 4) {
+            _exit(1);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+/// The source of the forkserver runtime linked by the `FORKSERVER`
+/// directive.
+pub(crate) const RUNTIME: &str = include_str!("forkserver.c");
+
+#[cfg(unix)]
+pub use self::unix::Forkserver;
+
+#[cfg(unix)]
+mod unix {
+    use crate::{
+        execution::{self, Outcome},
+        limit::Resource,
+    };
+    use std::{
+        fs::File,
+        io::{self, Read, Seek, SeekFrom, Write},
+        os::unix::{
+            io::{AsRawFd, FromRawFd, RawFd},
+            process::{CommandExt, ExitStatusExt},
+        },
+        process::{Child, Command, ExitStatus, Stdio},
+        time::{Duration, Instant},
+    };
+    use tempfile::TempDir;
+
+    /// The descriptor on which the forkserver reads its requests, and the
+    /// next one, on which it writes its replies.
+    const FORKSRV_FD: RawFd = 198;
+
+    /// How long the program has to say hello.
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A driver of a program built with the `FORKSERVER` directive. It is
+    /// returned by [`Assert::forkserver`][crate::Assert::forkserver].
+    ///
+    /// The protocol is modelled on the classic AFL forkserver, but it is
+    /// not compatible with the `ForkserverExecutor` of LibAFL nor with
+    /// AFL++: the runtime sends the legacy hello, and has no shared memory
+    /// coverage map.
+    ///
+    /// Each input is written to the standard input of a child forked from
+    /// the program, and its outcome is read from the forkserver. The
+    /// outputs of the children are discarded.
+    pub struct Forkserver {
+        process: Child,
+        control: File,
+        status: File,
+        input: File,
+        limits: Vec<(Resource, u64)>,
+        timeout: Option<Duration>,
+        was_killed: bool,
+        _working_directory: TempDir,
+    }
+
+    impl Forkserver {
+        /// Spawn the program, with the control and status pipes on
+        /// descriptors 198 and 199, and wait for its hello.
+        pub(crate) fn spawn(
+            mut command: Command,
+            limits: &[(Resource, u64)],
+            fixtures: &[(std::path::PathBuf, Vec<u8>)],
+        ) -> io::Result<Self> {
+            let working_directory = execution::working_directory(fixtures)?;
+            let input = tempfile::tempfile_in(working_directory.path())?;
+            let (control_read, control) = pipe()?;
+            let (mut status, status_write) = pipe()?;
+            let (control_fd, status_fd) = (control_read.as_raw_fd(), status_write.as_raw_fd());
+
+            // SAFETY: `dup2` is async-signal-safe, and it clears the
+            // close-on-exec flag of the new descriptors.
+            unsafe {
+                command.pre_exec(move || {
+                    for (from, to) in [(control_fd, FORKSRV_FD), (status_fd, FORKSRV_FD + 1)] {
+                        if libc::dup2(from, to) < 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+
+                    Ok(())
+                });
+            }
+
+            let process = command
+                .current_dir(working_directory.path())
+                .stdin(input.try_clone()?)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+
+            // The pipes are closed when the program exits, once the ends
+            // of the program are only open in it.
+            drop((control_read, status_write));
+
+            let hello = read(&mut status, Some(HANDSHAKE_TIMEOUT))
+                .and_then(|hello| hello.ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut)));
+
+            let mut forkserver = Self {
+                process,
+                control,
+                status,
+                input,
+                limits: limits.to_vec(),
+                timeout: None,
+                was_killed: false,
+                _working_directory: working_directory,
+            };
+
+            match hello {
+                Ok(_) => Ok(forkserver),
+                Err(error) => {
+                    forkserver.kill();
+
+                    Err(io::Error::new(
+                        error.kind(),
+                        format!(
+                            "The program did not start a forkserver, it must be built with the `FORKSERVER` directive: {}",
+                            error
+                        ),
+                    ))
+                }
+            }
+        }
+
+        /// Kill the children running for longer than `timeout`, whose
+        /// outcome is then [`Outcome::TimedOut`]. There is no timeout by
+        /// default.
+        pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+            self.timeout = Some(timeout);
+            self
+        }
+
+        /// Fork a child, deliver the input on its standard input, and
+        /// return how it ended.
+        pub fn run(&mut self, input: &[u8]) -> io::Result<Outcome> {
+            // The children share the offset of the input with the driver.
+            self.input.set_len(0)?;
+            self.input.seek(SeekFrom::Start(0))?;
+            self.input.write_all(input)?;
+            self.input.seek(SeekFrom::Start(0))?;
+
+            self.control
+                .write_all(&u32::from(self.was_killed).to_ne_bytes())?;
+            self.was_killed = false;
+
+            let pid = self
+                .reply(None)?
+                .expect("The reply is awaited without a timeout");
+
+            if let Some(status) = self.reply(self.timeout)? {
+                return Ok(Outcome::new(
+                    ExitStatus::from_raw(status),
+                    &self.limits,
+                    None,
+                ));
+            }
+
+            // SAFETY: the pid is not reused before the forkserver reaps the
+            // child, after it is killed.
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+
+            self.was_killed = true;
+
+            // The status of the killed child follows.
+            self.reply(None)?;
+
+            Ok(Outcome::TimedOut)
+        }
+
+        /// Read a reply of the forkserver, or `None` after the timeout.
+        fn reply(&mut self, timeout: Option<Duration>) -> io::Result<Option<i32>> {
+            match read(&mut self.status, timeout) {
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The forkserver exited",
+                )),
+                result => result,
+            }
+        }
+
+        fn kill(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    impl Drop for Forkserver {
+        fn drop(&mut self) {
+            self.kill();
+        }
+    }
+
+    /// Create a pipe, whose read end comes first, closed on `exec`.
+    fn pipe() -> io::Result<(File, File)> {
+        let mut fds = [0; 2];
+
+        // SAFETY: `fds` holds the two descriptors, which are owned by the
+        // files once the pipe is created. Where possible, the pipe is
+        // closed on `exec` atomically, so that the programs spawned by
+        // other threads do not inherit it.
+        unsafe {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            let result = libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC);
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            let result = libc::pipe(fds.as_mut_ptr());
+
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let files = (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]));
+
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            for fd in fds {
+                if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(files)
+        }
+    }
+
+    /// Read a 32-bit integer from the pipe, or `None` if none is readable
+    /// before the timeout.
+    fn read(pipe: &mut File, timeout: Option<Duration>) -> io::Result<Option<i32>> {
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            let mut poll = libc::pollfd {
+                fd: pipe.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let milliseconds = remaining.as_millis().min(libc::c_int::MAX as u128);
+
+                // SAFETY: `poll` is valid for reads and writes.
+                match unsafe { libc::poll(&mut poll, 1, milliseconds as libc::c_int) } {
+                    0 => return Ok(None),
+                    count if count > 0 => break,
+                    _ => {
+                        let error = io::Error::last_os_error();
+
+                        if error.kind() != io::ErrorKind::Interrupted {
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut bytes = [0; 4];
+        pipe.read_exact(&mut bytes)?;
+
+        Ok(Some(i32::from_ne_bytes(bytes)))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::{run, Language, Outcome};
+    use std::time::Duration;
+
+    #[test]
+    fn test_forkserver() {
+        let program = r#"
+            #inline_c_rs FORKSERVER
+            #include <stdio.h>
+            #include <stdlib.h>
+            #include <string.h>
+
+            int main() {
+                char input[16] = {0};
+
+                if (fgets(input, sizeof(input), stdin) == NULL) {
+                    return 1;
+                }
+
+                if (strcmp(input, "crash") == 0) {
+                    abort();
+                }
+
+                if (strcmp(input, "hang") == 0) {
+                    for (;;) {}
+                }
+
+                return (int) strlen(input);
+            }
+        "#;
+        let mut assert = run(Language::C, program).unwrap();
+        let mut forkserver = assert.forkserver().unwrap();
+
+        forkserver.timeout(Duration::from_millis(500));
+
+        assert_eq!(forkserver.run(b"").unwrap(), Outcome::Exited(1));
+        assert_eq!(forkserver.run(b"abc").unwrap(), Outcome::Exited(3));
+        assert_eq!(
+            forkserver.run(b"crash").unwrap(),
+            Outcome::Signaled(libc::SIGABRT)
+        );
+        assert_eq!(forkserver.run(b"hang").unwrap(), Outcome::TimedOut);
+        assert_eq!(forkserver.run(b"a").unwrap(), Outcome::Exited(1));
+
+        // Without a driver, the program runs normally.
+        assert.failure().code(1);
+
+        // The runtime is compiled in the language of the program.
+        assert_eq!(
+            run(
+                Language::Cxx,
+                "#inline_c_rs FORKSERVER\nint main() { return 2; }"
+            )
+            .unwrap()
+            .forkserver()
+            .unwrap()
+            .run(b"")
+            .unwrap(),
+            Outcome::Exited(2)
+        );
+
+        let error = run(Language::C, "int main() { return 0; }")
+            .unwrap()
+            .forkserver()
+            .err()
+            .expect("The program has no forkserver");
+
+        assert!(error.to_string().contains("`FORKSERVER`"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{ffi::OsString, path::Path, process::Command};
+
+/// A tool counting the instructions executed by a program, used by
+/// [`Assert::count_instructions`][crate::Assert::count_instructions].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstructionCounter {
+    /// `valgrind --tool=callgrind`, which simulates the CPU, and gives the
+    /// same count for every run of a deterministic program.
+    Callgrind,
+    /// `perf stat`, which reads the hardware counters. It is much faster,
+    /// but its counts vary slightly between runs.
+    Perf,
+}
+
+impl InstructionCounter {
+    /// Return the first instruction counter that is installed, preferring
+    /// callgrind.
+    pub fn detect() -> Option<Self> {
+        [Self::Callgrind, Self::Perf]
+            .iter()
+            .copied()
+            .find(|counter| counter.is_installed())
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Callgrind => "valgrind",
+            Self::Perf => "perf",
+        }
+    }
+
+    fn is_installed(self) -> bool {
+        Command::new(self.program())
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    /// The command running a program with the counter, before the program,
+    /// writing its report to `report`.
+    pub(crate) fn command(self, report: &Path) -> Vec<OsString> {
+        match self {
+            Self::Callgrind => {
+                let mut report_file = OsString::from("--callgrind-out-file=");
+                report_file.push(report);
+
+                vec![
+                    "valgrind".into(),
+                    "--tool=callgrind".into(),
+                    "--quiet".into(),
+                    report_file,
+                ]
+            }
+            Self::Perf => vec![
+                "perf".into(),
+                "stat".into(),
+                "-x,".into(),
+                "-e".into(),
+                "instructions:u".into(),
+                "-o".into(),
+                report.into(),
+                "--".into(),
+            ],
+        }
+    }
+
+    /// Read the number of instructions from the report of the counter.
+    pub(crate) fn parse(self, report: &str) -> Option<u64> {
+        match self {
+            // The total of the events, e.g. `summary: 123456`.
+            Self::Callgrind => report.lines().find_map(|line| {
+                line.strip_prefix("summary:")
+                    .or_else(|| line.strip_prefix("totals:"))?
+                    .split_whitespace()
+                    .next()?
+                    .parse()
+                    .ok()
+            }),
+            // A CSV line per event, e.g. `123456,,instructions:u,…`.
+            Self::Perf => report.lines().find_map(|line| {
+                let mut fields = line.split(',');
+                let count = fields.next()?;
+
+                if fields.nth(1)?.starts_with("instructions") {
+                    count.parse().ok()
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Language, Skipped};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            InstructionCounter::Callgrind.parse(
+                "# callgrind format\nversion: 1\ncmd: ./a.out\n\nevents: Ir\n\nfn=main\n3 12\n\nsummary: 104587\ntotals: 104587\n"
+            ),
+            Some(104587)
+        );
+        assert_eq!(
+            InstructionCounter::Perf.parse(
+                "# started on Mon Jan  1 00:00:00 2024\n\n104312,,instructions:u,1043210,100.00,,\n"
+            ),
+            Some(104312)
+        );
+        assert_eq!(
+            InstructionCounter::Perf.parse("<not supported>,,instructions:u,0,100.00,,\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_count_instructions() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                int main() {
+                    volatile int sum = 0;
+
+                    for (int i = 0; i < 1000000; i++) {
+                        sum += i;
+                    }
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        match assert.count_instructions() {
+            Ok(instructions) => assert!(instructions > 1_000_000),
+            Err(error) => assert!(
+                error.is::<Skipped>() && InstructionCounter::detect().is_none(),
+                "{}",
+                error
+            ),
+        }
+    }
+}
@@ -43,9 +43,9 @@
 //!        #ifndef _MSC_VER
 //!        }
 //!        #endif
-//!    }
+//!    };
 //!
-//!    println!("{}", assert.output_path());
+//!    println!("{:?}", assert.output_path());
 //!}
 //!```
 //!
@@ -94,26 +94,622 @@
 //!        #ifndef _MSC_VER
 //!        }
 //!        #endif
-//!    }
+//!    };
 //!
-//!    println!("{}", assert.output_path());
+//!    println!("{:?}", assert.output_path());
 //!}
 //!```
 //!The above will compile to a windows DLL using the mingw toolchain. 
 //!
 //!## Macros
 //!
-//!The macro functionality is expanded upon from inline-c. In addition to `#define`, macro conditionals are also supported including `#ifdef`, `#else`, `#elif`, and `#endif`. However, only single-line macros are supported.
+//!The macro functionality is expanded upon from inline-c. Every preprocessor directive is supported, including `#define`, `#undef`, `#if`, `#ifdef`, `#ifndef`, `#elif`, `#else`, `#endif`, `#pragma`, `#error`, `#warning` and `#line`. Each directive is kept on its own line, and header names such as `<sys/types.h>` or `<x86intrin.h>` are passed through as written.
+//!
+//!Rust's tokenizer does not accept the `\` continuation character. Instead, a directive extends over all the lines of a group (`(…)`, `{…}` or `[…]`) opened on its first line; those lines are joined. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_swap() {
+//!    (assert_c! {
+//!        #define SWAP(a, b) do {
+//!            int tmp = (a);
+//!            (a) = (b);
+//!            (b) = tmp;
+//!        } while (0)
+//!
+//!        int main() {
+//!            int x = 1, y = 2;
+//!            SWAP(x, y);
+//!
+//!            return !(x == 2 && y == 1);
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Raw strings
+//!
+//!Some valid C cannot go through Rust's tokenizer: multi-character constants like `'ab'`, unbalanced braces inside `#if` branches, `$` in identifiers, comments, or the exact whitespace used with `#` stringification. Such code can be passed as a string literal instead, which is compiled verbatim. `#inline_c_rs` directives are still handled. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_raw_string() {
+//!    (assert_c!(r#"
+//!        #inline_c_rs SHARED
+//!
+//!        int $counter = 0;
+//!
+//!        int LLVMFuzzerTestOneInput(const unsigned char *data, unsigned long size) {
+//!            // Braces in disabled branches do not need to be balanced.
+//!        #if 0
+//!            {
+//!        #endif
+//!            return 0;
+//!        }
+//!    "#))
+//!    .output_path();
+//!}
+//!```
+//!
+//!When the token form is used, the macro warns about constructs that it cannot reproduce faithfully.
+//!
+//!## Interpolation
+//!
+//!Rust values can be interpolated in the C code with `#{expr}` placeholders, in both the token and the raw-string forms. The expressions are evaluated at runtime and formatted with [`CLiteral`]: strings become escaped C string literals, byte arrays become initializer lists, and numbers become numeric literals. [`Verbatim`] inserts a value as-is. A `#{` inside a C string or character literal, or inside a comment, is left as-is. The placeholders of `#inline_c_rs` lines are formatted with [`CLiteral::to_directive_value`] instead, for the directive grammar. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_interpolation(input: &[u8]) {
+//!    (assert_c! {
+//!        #define SIZE #{input.len()}
+//!
+//!        int main() {
+//!            const unsigned char input[SIZE] = #{input};
+//!
+//!            return input[0];
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Files
+//!
+//!Long harnesses can be kept in their own file with `assert_c_file!` or `assert_cxx_file!`. The path is relative to `CARGO_MANIFEST_DIR`, and the file is processed like the content of `assert_c!`, including `#inline_c_rs` directives. Editing the file rebuilds the test, and `#include "…"` is resolved relative to the directory of the file. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c_file;
+//!
+//!fn test_file() {
+//!    assert_c_file!("tests/fixtures/harness.c").success();
+//!}
+//!```
+//!
+//!## Test matrices
+//!
+//!`#[libafl_inline_c::test]` expands a test function into one test per combination of target triple, compiler and optimization level. The snippets of the test body are compiled with the configuration of each combination, and when the compiler of a combination is not installed, the C compiler for `assert_c!` or the C++ driver for `assert_cxx!`, the test is skipped at run time: it passes, with a `Skipped:` line on the standard error. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!#[libafl_inline_c::test(
+//!    targets = ["host", "x86_64-pc-windows-gnu"],
+//!    compilers = ["gcc", "clang"],
+//!    opt_levels = [0, 2],
+//!)]
+//!fn test_harness() {
+//!    (assert_c! {
+//!        int main() {
+//!            return 0;
+//!        }
+//!    })
+//!    .assert();
+//!}
+//!```
+//!
+//!The tests are generated in a module named after the function, e.g. `test_harness::x86_64_pc_windows_gnu_clang_o2`. The host target is written `"host"`, and a compiler can be a name such as `clang-18` or a full path. C++ snippets use the matching C++ driver, e.g. `clang++-18`.
+//!
+//!## Differential execution
+//!
+//!With `#inline_c_rs DIFFERENTIAL`, the program is compiled with every available compiler among `gcc` and `clang`, and each build is run. If their exit status, stdout or stderr differ, an error reports the first pair of compilers that disagree. Otherwise, the [`Assert`] of the first build is returned. The list of compilers can be configured with `#inline_c_rs DIFFERENTIAL_COMPILERS: "gcc clang-18 /opt/llvm/bin/clang"`; those that are not installed are skipped. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_differential() {
+//!    (assert_c! {
+//!        #inline_c_rs DIFFERENTIAL
+//!
+//!        #include <stdio.h>
+//!
+//!        int main() {
+//!            printf("%d\n", (int) sizeof(long));
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Compiler selection
+//!
+//!By default, the compiler is discovered by `cc-rs`, which honors the `CC` and `CXX` environment variables. A program can select it with `#inline_c_rs CC: "clang-18"` or `#inline_c_rs CXX: "/opt/llvm/bin/clang++"`, and tests can do the same with the [`Config`] builder. A C compiler also selects the matching C++ driver, e.g. `clang++-18`.
+//!
+//!The compiler that built a program, with its family and version, is available with [`Assert::compiler`]. `#inline_c_rs CC_MIN: "clang>=15"` requires a minimum version, possibly for several families as in `"clang>=15 gcc>=12"`. When the compiler does not meet it, `run` returns a [`Skipped`] error. Only the tests generated by `#[libafl_inline_c::test]` skip it: since the test harness cannot mark a running test as ignored, they pass, and write the `Skipped:` reason on the standard error. A plain `#[test]` fails with a message starting with `Skipped:`.
+//!
+//!With `#inline_c_rs HERMETIC` or [`Config::hermetic`], the compiler must be set explicitly, and the environment of the test process is ignored, so that the build does not depend on it: the compiler only gets the optimization level, the debug information and the warnings, without the `CFLAGS` or `CXXFLAGS` that `cc-rs` reads, and the `INLINE_C_RS_*` variables are not applied. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_hermetic() {
+//!    (assert_c! {
+//!        #inline_c_rs HERMETIC
+//!        #inline_c_rs CC: "gcc"
+//!        #inline_c_rs CC_MIN: "gcc>=9"
+//!
+//!        int main() {
+//!            return 0;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Optimization and debug information
+//!
+//!Programs are compiled without optimizations and without debug information by default. `#inline_c_rs OPT_LEVEL: "2"` selects the optimization level, one of `0`, `1`, `2`, `3`, `s` or `z`, and `#inline_c_rs DEBUG` generates debug information, e.g. to symbolize a crash. [`Config::opt_level`] and [`Config::debug`] do the same for every program compiled with a [`Config`]. They are translated for the compiler, e.g. `-O2 -gdwarf-4` for GCC and `-O2 -Z7` for MSVC, which caps the level at `2`. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_optimized() {
+//!    (assert_c! {
+//!        #inline_c_rs OPT_LEVEL: "2"
+//!        #inline_c_rs DEBUG
+//!
+//!        int main() {
+//!            return 0;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Language standards
+//!
+//!The language standard is the default of the compiler unless `#inline_c_rs STD: "c11"` or [`Config::standard`] selects one, e.g. `c99`, `gnu17`, `c++17` or `gnu++20`. It becomes `-std=c11` for GCC and Clang and `/std:c11` for MSVC, which has no GNU dialects. A C++ standard for a C program, or the other way around, is an error. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_cxx;
+//!
+//!fn test_standard() {
+//!    (assert_cxx! {
+//!        #inline_c_rs STD: "c++17"
+//!
+//!        int main() {
+//!            return __cplusplus == 201703L ? 0 : 1;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Include directories and definitions
+//!
+//!`#inline_c_rs INCLUDE: ["include", "vendor/my headers"]` adds include directories, and `#inline_c_rs DEFINE: NAME=VALUE` defines a preprocessor macro, the value being the rest of the line. Both can be repeated, and [`Config::include`] and [`Config::define`] do the same from Rust. Each directory and definition is passed as a single argument, so they can contain spaces without any quoting, with `-I`/`-D` or `/I`/`/D` depending on the compiler. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_definitions() {
+//!    (assert_c! {
+//!        #inline_c_rs DEFINE: GREETING="Hello, World!"
+//!        #inline_c_rs DEFINE: ANSWER=6 * 7
+//!
+//!        #include <stdio.h>
+//!
+//!        int main() {
+//!            printf("%s %d\n", GREETING, ANSWER);
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Flags
+//!
+//!The `CFLAGS`, `CPPFLAGS`, `CXXFLAGS` and `LDFLAGS` directives, or the `INLINE_C_RS_`-prefixed environment variables, are split like a shell would, so `#inline_c_rs CFLAGS: "-DANSWER='(6 * 7)'"` passes a single argument. The `LDFLAGS` are encoded for the compiler: GCC and Clang get the flags they understand, such as `-L`, `-l` or `-fuse-ld=lld`, as-is, and the linker-only flags, such as `--gc-sections`, with `-Wl,`. MSVC gets them after `/link`, with `-L` translated to `/LIBPATH:` and `-lfoo` to `foo.lib`.
+//!
+//!## Linking
+//!
+//!`#inline_c_rs LINK: "m pthread dl"` links libraries, as `-lm` for GCC and Clang or `m.lib` for MSVC, `#inline_c_rs LINK_SEARCH: ["vendor/lib"]` adds directories to search them in, and `#inline_c_rs OBJECTS: ["build/harness.o"]` links prebuilt object files or static libraries. `#inline_c_rs PKG_CONFIG: "zlib libxml-2.0"` runs `pkg-config`, or the program set in the `PKG_CONFIG` environment variable, and adds the compiler flags and the libraries of the packages. The libraries come after the program, the `LDFLAGS` and the objects, so that they resolve the symbols used before them. The [`Config::link`], [`Config::link_search`], [`Config::object`] and [`Config::pkg_config`] methods do the same from Rust. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_zlib() {
+//!    (assert_c! {
+//!        #inline_c_rs PKG_CONFIG: "zlib"
+//!        #inline_c_rs LINK: "m"
+//!
+//!        #include <zlib.h>
+//!
+//!        int main() {
+//!            return crc32(0, Z_NULL, 0) == 0 ? 0 : 1;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Environment of the program
+//!
+//!The settings, such as `TARGET`, `CC`, `CFLAGS` or `LDFLAGS`, configure the build only: they never reach the environment of the program, and an unknown `#inline_c_rs NAME: "value"` setting is an error. The environment of the program is set with `#inline_c_rs ENV: NAME=VALUE`, the value being the rest of the line or a quoted string, or with the `INLINE_C_RS_ENV_NAME` environment variable; the compiler does not see it. `#inline_c_rs ENV_REMOVE: "HOME TMPDIR"` removes inherited variables, and `#inline_c_rs ENV_CLEAR` starts from an empty environment, so that the harness runs in a predictable environment. [`Config::env`], [`Config::env_remove`] and [`Config::env_clear`] do the same for every program compiled with a [`Config`], and [`Assert::env`], [`Assert::env_remove`] and [`Assert::env_clear`] for a single run. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_env() {
+//!    (assert_c! {
+//!        #inline_c_rs ENV_CLEAR
+//!        #inline_c_rs ENV: GREETING="Hello, World!"
+//!
+//!        #include <stdio.h>
+//!        #include <stdlib.h>
+//!
+//!        int main() {
+//!            printf("%s\n", getenv("GREETING"));
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .success();
+//!}
+//!```
+//!
+//!## Directive grammar
+//!
+//!An `#inline_c_rs` directive stands on its own line, and is made of a name, optionally followed by `:` and a value. Depending on the directive, the value is:
+//!
+//!- nothing, `true` or `false` for the flags, e.g. `#inline_c_rs SHARED` or `#inline_c_rs SHARED: false`;
+//!- a string, with the `\\`, `\"`, `\'`, `\n`, `\r`, `\t`, `\0`, `\x7f` and `\u{1f600}` escapes, or an integer, e.g. `#inline_c_rs CC: "clang"` or `#inline_c_rs OPT_LEVEL: 2`;
+//!- a string or a list of strings, which can span several lines, e.g. `#inline_c_rs INCLUDE: ["include", "vendor/include"]`;
+//!- a name with an optional value, the rest of the line, e.g. `#inline_c_rs DEFINE: NAME=VALUE`;
+//!- a string argument before `:`, and a string, e.g. `#inline_c_rs FIXTURE "input.txt": "contents"`.
+//!
+//!With the macros, mistakes are reported at compile time, with a suggestion for a misspelled name, e.g. ``unknown directive `SHARD`, did you mean `SHARED`?``. With `run`, they are reported as an error.
+//!
+//!## Scoped configuration
+//!
+//!A [`Config`] configures the compilation without touching the environment of the test process, so that tests running in parallel do not leak settings to each other. It can be passed explicitly with [`Config::run`], or installed for the programs compiled by the current thread with the macros, until the returned [`ConfigGuard`] is dropped:
+//!
+//!```rust
+//!use libafl_inline_c::{assert_c, Config};
+//!
+//!fn test_scoped_config() {
+//!    let _guard = Config::new()
+//!        .opt_level("2")
+//!        .define("ANSWER", "42")
+//!        .install();
+//!
+//!    (assert_c! {
+//!        int main() {
+//!            return ANSWER;
+//!        }
+//!    })
+//!    .failure()
+//!    .code(42);
+//!}
+//!```
+//!
+//!From the lowest precedence to the highest, the settings come from:
+//!
+//!1. the `INLINE_C_RS_*` environment variables, e.g. `INLINE_C_RS_CC` or `INLINE_C_RS_ENV_NAME`, which apply to the whole process;
+//!2. the scoped [`Config`];
+//!3. the `#inline_c_rs` directives of the program.
+//!
+//!A setting such as `CC` or `OPT_LEVEL` overrides the same setting of a lower layer, and setting `CC` without `CXX` also selects the matching C++ driver. The repeatable ones, such as `INCLUDE`, `DEFINE` or `ENV`, accumulate in that order, so that the last `ENV` of a variable wins.
+//!
+//!## Execution
+//!
+//![`Assert::execute`] runs the program and returns an [`Execution`], with its standard output and error, how it ended as an [`Outcome`], its wall time, and, on Unix, the user and system CPU time and the peak resident set size reported by `wait4`. Each output is captured up to 16 MiB by default, which [`Assert::output_limit`] changes, so that a runaway loop cannot exhaust the memory of the test. For example:
+//!
+//!```rust
+//!use libafl_inline_c::{assert_c, Outcome};
+//!
+//!fn test_execution() {
+//!    let execution = (assert_c! {
+//!        #include <stdio.h>
+//!
+//!        int main() {
+//!            printf("Hello, World!\n");
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .output_limit(1024)
+//!    .execute()
+//!    .unwrap();
+//!
+//!    assert_eq!(execution.outcome(), Outcome::Exited(0));
+//!    assert_eq!(execution.stdout(), b"Hello, World!\n");
+//!    assert!(execution.peak_rss().unwrap() < 64 << 20);
+//!}
+//!```
+//!
+//!## Resource limits
+//!
+//!`#inline_c_rs RLIMIT: NAME=LIMIT` limits a resource of the program on Unix, to check a harness under the limits applied by the fuzzer, such as its memory limit. The resources are `AS` for the address space, `CPU` for the CPU time in seconds, `FSIZE` for the size of the written files, `NOFILE` for the number of open files, and `CORE` for the size of the core dumps. Sizes can have a `K`, `M` or `G` suffix. [`Config::rlimit`] and [`Assert::rlimit`] do the same with a [`Resource`]. The soft limit is set before the program starts, without raising it above the hard limit.
+//!
+//!A program killed for exceeding its CPU time or file size limit ends with [`Outcome::LimitExceeded`]. A program exceeding its address space or open files limit sees its allocations or opens fail instead, and ends as it handles them: a crash is reported as [`Outcome::Signaled`], since it may be unrelated to the limit. For example:
+//!
+//!```rust
+//!use libafl_inline_c::{assert_c, Outcome, Resource};
+//!
+//!fn test_rlimit() {
+//!    let execution = (assert_c! {
+//!        #inline_c_rs RLIMIT: CPU=1
+//!
+//!        int main() {
+//!            for (volatile int i = 0; ; i++) {}
+//!        }
+//!    })
+//!    .execute()
+//!    .unwrap();
+//!
+//!    assert_eq!(
+//!        execution.outcome(),
+//!        Outcome::LimitExceeded(Resource::CpuTime)
+//!    );
+//!}
+//!```
+//!
+//!## Working directory and fixtures
+//!
+//!Each run of the program happens in a new temporary working directory, so that the files it reads and writes do not depend on the directory of the tests, nor on the other runs. `#inline_c_rs FIXTURE "path": "contents"` writes a file in it before the program runs, as do [`Config::fixture`] and [`Assert::fixture`] with bytes. The [`Execution`] returned by [`Assert::execute`] keeps the working directory until it is dropped: [`Execution::file`] reads a file of it, and [`Execution::changed_files`] lists the files that the program created or changed, and [`Execution::deleted_files`] the fixtures that it removed. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_fixtures() {
+//!    let execution = (assert_c! {
+//!        #inline_c_rs FIXTURE "input.txt": "Hello, World!"
+//!        #include <stdio.h>
+//!
+//!        int main() {
+//!            char line[32] = {0};
+//!            FILE* input = fopen("input.txt", "r");
+//!            FILE* output = fopen("output.txt", "w");
+//!
+//!            fgets(line, sizeof(line), input);
+//!            fprintf(output, "%s\n", line);
+//!
+//!            return fclose(output);
+//!        }
+//!    })
+//!    .execute()
+//!    .unwrap();
+//!
+//!    assert!(execution.success());
+//!    assert_eq!(execution.file("output.txt").unwrap(), b"Hello, World!\n");
+//!}
+//!```
+//!
+//!## Snapshots
+//!
+//![`Assert::snapshot`] runs the program and compares its outcome, standard output and standard error with a snapshot file, stored in the `snapshots` directory next to the source of the test, e.g. `src/snapshots/lib__greeting.snap` for a test in `src/lib.rs`. Running the tests with `INLINE_C_RS_UPDATE_SNAPSHOTS=1` writes the snapshots instead, to create or update them. Values that change from one run to another are replaced before the comparison: addresses such as `0x7ffd5e8c3a1c`, or `00007FFD5E8C3A1C` as printed by MSVC on 64-bit targets, by `[ADDRESS]`, and the matches of the regular expressions given to [`Assert::redact`]. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_snapshot() {
+//!    (assert_c! {
+//!        #include <stdio.h>
+//!
+//!        int main() {
+//!            int x = 42;
+//!
+//!            printf("x = %d at %p\n", x, (void*) &x);
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .redact("[0-9]+ ms", "[DURATION]")
+//!    .snapshot("greeting");
+//!}
+//!```
+//!
+//!## Benchmarks
+//!
+//![`Assert::bench`] runs the program a given number of times, after the warm-up runs set with [`Assert::warm_up`], and returns the [`Statistics`] of their wall times: the minimum, median, mean and standard deviation. [`Statistics::assert_median_below`] fails the test when the median exceeds a threshold. For a fuzzing harness built with `#inline_c_rs SHARED`, [`Assert::bench_fuzzer`] loads the library in the test process, and times the calls of `LLVMFuzzerTestOneInput` with each input, to give the executions per second. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!use std::time::Duration;
+//!
+//!fn test_bench() {
+//!    let statistics = (assert_c! {
+//!        #inline_c_rs SHARED
+//!        #include <stddef.h>
+//!        #include <stdint.h>
+//!
+//!        int LLVMFuzzerTestOneInput(const uint8_t* data, size_t size) {
+//!            return 0;
+//!        }
+//!    })
+//!    .warm_up(10)
+//!    .bench_fuzzer(&[b"Hello", b"World"], 1000)
+//!    .unwrap();
+//!
+//!    println!("{}", statistics);
+//!    assert!(statistics.executions_per_second() > 10_000.0);
+//!    statistics.assert_median_below(Duration::from_micros(100));
+//!}
+//!```
+//!
+//!## Instruction counts
+//!
+//!Wall times are noisy on shared machines. [`Assert::count_instructions`] runs the program once under `valgrind --tool=callgrind`, or `perf stat` when callgrind is not installed, and returns the number of instructions it executed, to catch performance regressions without timing jitter. [`Assert::count_instructions_with`] selects the [`InstructionCounter`]. When neither tool is installed, a [`Skipped`] error is returned. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_instructions() {
+//!    let instructions = (assert_c! {
+//!        int main() {
+//!            volatile int sum = 0;
+//!
+//!            for (int i = 0; i < 1000; i++) {
+//!                sum += i;
+//!            }
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .count_instructions()
+//!    .unwrap();
+//!
+//!    assert!(instructions < 1_000_000);
+//!}
+//!```
+//!
+//!## Source coverage
+//!
+//!`#inline_c_rs SOURCE_COVERAGE` instruments the program for source coverage, with `--coverage` for GCC and `-fprofile-instr-generate -fcoverage-mapping` for Clang, and [`Config::source_coverage`] does the same for every program compiled with a [`Config`]. Each run then writes its own profile, which is read with `gcov`, or merged with `llvm-profdata` and exported with `llvm-cov`, and [`Execution::coverage`] returns the hit counts of its lines and branches as a [`Coverage`]. When these tools are not installed, the compilation returns a [`Skipped`] error. Lines can be found by their text, e.g. to assert that an input reached the error handling of the program. For example:
+//!
+//!```rust
+//!use libafl_inline_c::assert_c;
+//!
+//!fn test_coverage() {
+//!    let execution = (assert_c! {
+//!        #inline_c_rs SOURCE_COVERAGE
+//!        #include <stdlib.h>
+//!
+//!        int main() {
+//!            if (getenv("INPUT") == NULL) {
+//!                return 1; // error handling
+//!            }
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .env_remove("INPUT")
+//!    .execute()
+//!    .unwrap();
+//!
+//!    assert_eq!(execution.coverage().unwrap().hits("// error handling"), Some(1));
+//!}
+//!```
+//!
+//!When `INLINE_C_RS_LCOV` is set to a path, the coverage of every execution is appended to it as an lcov record, to produce a report of the whole test suite, e.g. with `genhtml`. The sources of the programs are copied in an `inline-c-rs-sources` directory next to it.
+//!
+//!## Async
+//!
+//!`run` and [`Assert::execute`] block until the compiler and the program end. [`run_async`], [`Config::run_async`] and [`Assert::execute_async`] return futures instead, which wait for them in another thread, so that the threads of the executor are not tied up. They work with any executor, e.g. tokio or async-std, and the configuration installed by the current thread is read when they are called. For example:
+//!
+//!```rust
+//!use libafl_inline_c::{run_async, Language};
+//!
+//!async fn test_async() {
+//!    let mut assert = run_async(Language::C, "int main() { return 0; }")
+//!        .await
+//!        .unwrap();
+//!    let execution = assert.execute_async().await.unwrap();
+//!
+//!    assert!(execution.success());
+//!}
+//!```
+//!
+//!## Batch compilation
+//!
+//!Large suites compile many programs, and running their tests in parallel can start more compilers than there are CPUs. [`run_batch`] and [`Config::run_batch`] compile many snippets concurrently, and return one result per snippet, in the same order. At most [`Config::jobs`] snippets are compiled at once, which defaults to the available parallelism. When cargo or make provide a jobserver, e.g. with `CARGO_MAKEFLAGS`, each compilation besides the first one also waits for one of its tokens. For example:
+//!
+//!```rust
+//!use libafl_inline_c::{Config, Language};
+//!
+//!fn test_batch() {
+//!    let snippets = (0..16).map(|code| (Language::C, format!("int main() {{ return {}; }}", code)));
+//!
+//!    for (code, result) in Config::new().jobs(4).run_batch(snippets).into_iter().enumerate() {
+//!        result.unwrap().assert().code(code as i32);
+//!    }
+//!}
+//!```
+//!
+//!## Forkserver
+//!
+//!`#inline_c_rs FORKSERVER` links a small forkserver runtime into the program, which speaks a minimal protocol modelled on the classic AFL forkserver, on the descriptors 198 and 199. Only the driver of this crate speaks it: the runtime sends the legacy hello, which the `ForkserverExecutor` of LibAFL and AFL++ reject, and it has no shared memory coverage map. [`Config::forkserver`] does the same for every program compiled with a [`Config`]. On Unix, [`Assert::forkserver`] starts the program as a forkserver and returns a [`Forkserver`] driver, which does the handshake, and then, for each input, requests a fork, delivers the input on the standard input of the child, and returns its [`Outcome`]. Without a driver, the program runs normally. For example:
+//!
+//!```rust
+//!use libafl_inline_c::{assert_c, Outcome};
+//!use std::time::Duration;
+//!
+//!fn test_forkserver() {
+//!    let mut forkserver = (assert_c! {
+//!        #inline_c_rs FORKSERVER
+//!        #include <stdio.h>
+//!        #include <stdlib.h>
+//!
+//!        int main() {
+//!            if (getchar() == 'x') {
+//!                abort();
+//!            }
+//!
+//!            return 0;
+//!        }
+//!    })
+//!    .forkserver()
+//!    .unwrap();
+//!
+//!    forkserver.timeout(Duration::from_secs(1));
+//!
+//!    assert_eq!(forkserver.run(b"a").unwrap(), Outcome::Exited(0));
+//!    assert_eq!(forkserver.run(b"x").unwrap(), Outcome::Signaled(6));
+//!}
+//!```
 
 
 
 
 mod assert;
+mod batch;
+mod bench;
+mod blocking;
+mod compiler;
+mod coverage;
+mod execution;
+mod flags;
+mod forkserver;
+mod instructions;
+mod limit;
+mod literal;
 mod run;
+mod skip;
+mod snapshot;
 
-pub use crate::run::{run, Language};
+pub use crate::run::{
+    install_matrix, run, run_async, run_batch, run_file, Config, ConfigGuard, Language,
+};
 pub use assert::Assert;
-pub use libafl_inline_c_macro::{assert_c, assert_cxx};
+pub use bench::Statistics;
+pub use compiler::{Compiler, CompilerFamily, CompilerVersion};
+pub use coverage::Coverage;
+pub use execution::{Execution, Outcome};
+#[cfg(unix)]
+pub use forkserver::Forkserver;
+pub use instructions::InstructionCounter;
+pub use libafl_inline_c_macro::{assert_c, assert_c_file, assert_cxx, assert_cxx_file, test};
+pub use limit::Resource;
+pub use literal::{CLiteral, Verbatim};
+pub use skip::{fail, skippable, Skipped, SkippedTest};
 pub mod predicates {
     //! Re-export the prelude of the `predicates` crate, which is useful for assertions.
     //!
@@ -126,7 +722,7 @@ pub mod predicates {
     //! helpful.
     //!
     //! ```rust
-    //! use inline_c::{assert_c, predicates::*};
+    //! use libafl_inline_c::{assert_c, predicates::*};
     //!
     //! fn test_predicates() {
     //!     (assert_c! {
@@ -151,9 +747,9 @@ pub mod predicates {
 #[cfg(test)]
 mod tests {
     use super::predicates::*;
-    use super::*;
-    use crate as inline_c;
-    use std::env::{remove_var, set_var};
+    use super::{assert_c, assert_c_file, assert_cxx};
+    use crate as libafl_inline_c;
+    use crate::Config;
 
     #[test]
     fn test_c_macro() {
@@ -186,12 +782,14 @@ mod tests {
 
     #[test]
     fn test_c_macro_with_env_vars_inlined() {
-        set_var("INLINE_C_RS_CFLAGS", "-D_CRT_SECURE_NO_WARNINGS");
+        let _guard = Config::new()
+            .define("_CRT_SECURE_NO_WARNINGS", None)
+            .install();
 
         (assert_c! {
             // Those are env variables.
-            #inline_c_rs FOO: "bar baz qux"
-            #inline_c_rs HELLO: "World!"
+            #inline_c_rs ENV: FOO="bar baz qux"
+            #inline_c_rs ENV: HELLO=World!
 
             #include <stdio.h>
             #include <stdlib.h>
@@ -218,16 +816,16 @@ mod tests {
             )
             .normalize(),
         );
-
-        remove_var("INLINE_C_RS_CFLAGS");
     }
 
     #[test]
-    fn test_c_macro_with_env_vars_from_env_vars() {
-        // Define env vars through env vars.
-        set_var("INLINE_C_RS_FOO", "bar baz qux");
-        set_var("INLINE_C_RS_HELLO", "World!");
-        set_var("INLINE_C_RS_CFLAGS", "-D_CRT_SECURE_NO_WARNINGS");
+    fn test_c_macro_with_env_vars_from_installed_config() {
+        // Define env vars through the installed configuration.
+        let _guard = Config::new()
+            .env("FOO", "bar baz qux")
+            .env("HELLO", "World!")
+            .define("_CRT_SECURE_NO_WARNINGS", None)
+            .install();
 
         (assert_c! {
             #include <stdio.h>
@@ -255,13 +853,56 @@ mod tests {
             )
             .normalize(),
         );
+    }
+
+    #[test]
+    fn test_c_macro_with_fixture() {
+        let execution = (assert_c! {
+            #inline_c_rs FIXTURE "greeting.txt": "Hello, World!"
+            #include <stdio.h>
 
-        remove_var("INLINE_C_RS_FOO");
-        remove_var("INLINE_C_RS_HELLO");
-        remove_var("INLINE_C_RS_CFLAGS");
+            int main() {
+                char greeting[32] = {0};
+                FILE* file = fopen("greeting.txt", "r");
+
+                if (file == NULL || fgets(greeting, sizeof(greeting), file) == NULL) {
+                    return 1;
+                }
+
+                printf("%s\n", greeting);
+
+                return 0;
+            }
+        })
+        .execute()
+        .unwrap();
+
+        execution
+            .assert()
+            .success()
+            .stdout(predicate::eq("Hello, World!\n").normalize());
+        assert!(execution.changed_files().unwrap().is_empty());
+        assert!(execution.deleted_files().is_empty());
+    }
+
+    #[test]
+    fn test_c_macro_with_snapshot() {
+        (assert_c! {
+            #include <stdio.h>
+
+            int main() {
+                int x = 42;
+
+                printf("x = %d at %p\n", x, (void*) &x);
+                fprintf(stderr, "took 12 ms\n");
+
+                return 1;
+            }
+        })
+        .redact("[0-9]+ ms", "[DURATION]")
+        .snapshot("c_macro_with_snapshot");
     }
 
-    /* #[cfg(nightly)]
     #[test]
     fn test_c_macro_with_define() {
         (assert_c! {
@@ -272,5 +913,225 @@ mod tests {
             }
         })
         .success();
-    } */
+    }
+
+    #[test]
+    fn test_c_macro_with_conditional_directives() {
+        (assert_c! {
+            #include <sys/types.h>
+            #include <stddef.h>
+
+            #define FOO 1
+            #undef FOO
+
+            #ifndef FOO
+            #define BAR 2
+            #endif
+
+            #if defined(BAR) && BAR == 2
+            #define RESULT 0
+            #elif 1
+            #error "unreachable"
+            #else
+            #define RESULT 1
+            #endif
+
+            #pragma once
+
+            int main() {
+                return RESULT;
+            }
+        })
+        .success();
+    }
+
+    #[test]
+    fn test_c_macro_with_multi_line_define() {
+        (assert_c! {
+            #define SWAP(a, b) do {
+                int tmp = (a);
+                (a) = (b);
+                (b) = tmp;
+            } while (0)
+            #define STRINGIFY(x) #x
+
+            #include <string.h>
+
+            int main() {
+                int x = 1;
+                int y = 2;
+
+                SWAP(x, y);
+
+                return !(x == 2 && y == 1 && strcmp(STRINGIFY(hello), "hello") == 0);
+            }
+        })
+        .success();
+    }
+
+    // Comments are dropped with a warning.
+    #[allow(deprecated)]
+    #[test]
+    fn test_c_macro_with_doc_comment() {
+        (assert_c! {
+            /** The answer. */ int answer = 42;
+
+            int main() {
+                return answer - 42;
+            }
+        })
+        .success();
+    }
+
+    #[test]
+    fn test_c_macro_with_interpolation() {
+        let size = 4usize;
+        let input = "a \"quoted\"\n";
+        let bytes = [0xde, 0xad, 0xbe, 0xef];
+
+        (assert_c! {
+            #include <stdio.h>
+            #include <string.h>
+
+            #define SIZE #{size}
+
+            int main() {
+                const unsigned char bytes[SIZE] = #{bytes};
+
+                printf("%s", #{input});
+
+                return !(bytes[3] == 0xef && strlen(#{input}) == #{input.len()});
+            }
+        })
+        .success()
+        .stdout(predicate::eq(input).normalize());
+
+        (assert_c!(
+            r#"
+            int main() {
+                return #{size * 2} - 8;
+            }
+            "#
+        ))
+        .success();
+
+        // The minimums keep the type of their Rust counterpart.
+        (assert_c! {
+            #include <limits.h>
+            #include <stdint.h>
+
+            int main() {
+                return !(#{i32::MIN} == INT_MIN
+                    && sizeof(#{i32::MIN}) == sizeof(int)
+                    && #{i64::MIN} == INT64_MIN
+                    && sizeof(#{i64::MIN}) == sizeof(long long)
+                    && #{u64::MAX} == UINT64_MAX);
+            }
+        })
+        .success();
+
+        // Only the placeholders of the code are replaced, and those of the
+        // directives are formatted for them.
+        let greeting = "Hello ??= \"é\"\x01";
+
+        (assert_c!(
+            r##"
+            #inline_c_rs ENV: GREETING=#{greeting}
+            #inline_c_rs DEFINE: SIZE=#{size}
+            #include <stdio.h>
+            #include <stdlib.h>
+            #include <string.h>
+
+            int main() {
+                /* #{ */
+                const char quote = '"';
+                const char *text = "#{size}"; // #{
+
+                printf("%s", getenv("GREETING"));
+
+                return !(quote == '"' && strcmp(text, "#" "{size}") == 0 && SIZE == #{size});
+            }
+            "##
+        ))
+        .success()
+        .stdout(predicate::eq(greeting));
+    }
+
+    #[crate::test(compilers = ["cc", "inline-c-rs-missing-compiler"], opt_levels = [0, 2])]
+    fn test_c_macro_in_matrix() {
+        let optimized = Config::current().settings().get("OPT_LEVEL") == Some(&String::from("2"));
+
+        (assert_c! {
+            int main() {
+            #if defined(__OPTIMIZE__)
+                return 2;
+            #else
+                return 0;
+            #endif
+            }
+        })
+        .assert()
+        .code(if optimized { 2 } else { 0 });
+
+        (assert_cxx! {
+            #include <string>
+
+            int main() {
+                return std::string("a").size() - 1;
+            }
+        })
+        .success();
+    }
+
+    #[crate::test(compilers = ["gcc"])]
+    fn test_c_macro_skipped_in_matrix() {
+        (assert_c! {
+            #inline_c_rs CC_MIN: "gcc>=999"
+
+            int main() {
+                return 1;
+            }
+        })
+        .success();
+
+        unreachable!("The program should be skipped");
+    }
+
+    #[test]
+    fn test_c_file_macro() {
+        assert_c_file!("tests/fixtures/harness.c")
+            .success()
+            .stdout(predicate::eq("Hello from a file!\n").normalize());
+    }
+
+    #[test]
+    fn test_c_macro_with_raw_string() {
+        (assert_c!(
+            r#"
+            #inline_c_rs ENV: FOO=bar
+
+            #include <stdio.h>
+            #include <stdlib.h>
+            #include <string.h>
+
+            #define STRINGIFY(x) #x
+
+            int main() {
+                int ab = 'ab';
+                int $dollar = 1;
+
+                // Braces in unused branches do not need to be balanced.
+            #if 0
+                {
+            #endif
+
+                printf("%s %s\n", STRINGIFY(a  +   b), getenv("FOO"));
+
+                return !(ab != 0 && $dollar == 1);
+            }
+            "#
+        ))
+        .success()
+        .stdout(predicate::eq("a + b bar\n").normalize());
+    }
 }
@@ -0,0 +1,305 @@
+use std::{fmt, fmt::Display, time::Duration};
+
+/// A resource of the executed program that can be limited, with
+/// [`Assert::rlimit`][crate::Assert::rlimit] or the `RLIMIT` directive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// The size of the virtual memory, in bytes, i.e. `RLIMIT_AS`.
+    AddressSpace,
+    /// The CPU time, in seconds, i.e. `RLIMIT_CPU`.
+    CpuTime,
+    /// The size of the files written, in bytes, i.e. `RLIMIT_FSIZE`.
+    FileSize,
+    /// The number of open file descriptors, i.e. `RLIMIT_NOFILE`.
+    OpenFiles,
+    /// The size of the core dumps, in bytes, i.e. `RLIMIT_CORE`.
+    CoreSize,
+}
+
+impl Resource {
+    const ALL: [Self; 5] = [
+        Self::AddressSpace,
+        Self::CpuTime,
+        Self::FileSize,
+        Self::OpenFiles,
+        Self::CoreSize,
+    ];
+
+    /// The name of the resource in the `RLIMIT` directive.
+    fn name(self) -> &'static str {
+        match self {
+            Self::AddressSpace => "AS",
+            Self::CpuTime => "CPU",
+            Self::FileSize => "FSIZE",
+            Self::OpenFiles => "NOFILE",
+            Self::CoreSize => "CORE",
+        }
+    }
+
+    fn is_size(self) -> bool {
+        matches!(self, Self::AddressSpace | Self::FileSize | Self::CoreSize)
+    }
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RLIMIT_{}", self.name())
+    }
+}
+
+/// Parse the limit of an `RLIMIT` directive, e.g. `AS=512M`. Sizes can
+/// have a `K`, `M` or `G` suffix, in powers of 1024.
+pub(crate) fn parse(name: &str, value: Option<&str>) -> Result<(Resource, u64), String> {
+    let resource = Resource::ALL
+        .iter()
+        .copied()
+        .find(|resource| resource.name() == name)
+        .ok_or_else(|| {
+            format!(
+                "Unknown resource `{}` in `RLIMIT`, expected one of AS, CPU, FSIZE, NOFILE or CORE",
+                name
+            )
+        })?;
+    let value = value
+        .map(str::trim)
+        .ok_or_else(|| format!("Missing limit of `{}` in `RLIMIT`", name))?;
+    let (digits, multiplier) = match value.char_indices().last() {
+        Some((index, suffix @ ('K' | 'M' | 'G'))) if resource.is_size() => (
+            &value[..index],
+            1 << (10 * (1 + "KMG".find(suffix).unwrap())),
+        ),
+        _ => (value, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|limit| limit.checked_mul(multiplier))
+        .map(|limit| (resource, limit))
+        .ok_or_else(|| format!("Invalid limit `{}` of `{}` in `RLIMIT`", value, name))
+}
+
+/// Set the soft limit of a resource of the current process, without
+/// raising it above the hard limit. It is called between `fork` and
+/// `exec`, and must be async-signal-safe.
+#[cfg(unix)]
+pub(crate) fn set(resource: Resource, limit: u64) -> std::io::Result<()> {
+    let resource = match resource {
+        Resource::AddressSpace => libc::RLIMIT_AS,
+        Resource::CpuTime => libc::RLIMIT_CPU,
+        Resource::FileSize => libc::RLIMIT_FSIZE,
+        Resource::OpenFiles => libc::RLIMIT_NOFILE,
+        Resource::CoreSize => libc::RLIMIT_CORE,
+    };
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    // SAFETY: `rlimit` is valid for reads and writes.
+    unsafe {
+        if libc::getrlimit(resource, &mut rlimit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        rlimit.rlim_cur = (limit as libc::rlim_t).min(rlimit.rlim_max);
+
+        if libc::setrlimit(resource, &rlimit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the limit that the program exceeded, from how it ended and the
+/// CPU time it used, if known.
+///
+/// Only unambiguous evidence is used: the kernel sends `SIGXCPU` and
+/// `SIGXFSZ` when the CPU time and file size limits are exceeded, and
+/// `SIGKILL` when the CPU time reaches the hard limit. Exceeding the
+/// address space or open files limits makes the allocations or the opens
+/// fail instead, which the program may handle or not, so a crash is never
+/// blamed on them.
+#[cfg(unix)]
+pub(crate) fn exceeded(
+    limits: &[(Resource, u64)],
+    signal: i32,
+    cpu_time: Option<Duration>,
+) -> Option<Resource> {
+    let limit = |resource| {
+        limits
+            .iter()
+            .find(|(limited, _)| *limited == resource)
+            .map(|(_, limit)| *limit)
+    };
+
+    match signal {
+        libc::SIGXCPU if limit(Resource::CpuTime).is_some() => Some(Resource::CpuTime),
+        libc::SIGXFSZ if limit(Resource::FileSize).is_some() => Some(Resource::FileSize),
+        libc::SIGKILL => match (limit(Resource::CpuTime), cpu_time) {
+            (Some(limit), Some(cpu_time)) if cpu_time >= Duration::from_secs(limit) => {
+                Some(Resource::CpuTime)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exceeded() {
+        let limits = [(Resource::CpuTime, 2), (Resource::AddressSpace, 1 << 28)];
+        let seconds = |seconds| Some(Duration::from_secs(seconds));
+
+        assert_eq!(
+            exceeded(&limits, libc::SIGXCPU, None),
+            Some(Resource::CpuTime)
+        );
+        assert_eq!(
+            exceeded(&limits, libc::SIGKILL, seconds(2)),
+            Some(Resource::CpuTime)
+        );
+        assert_eq!(exceeded(&limits, libc::SIGKILL, seconds(1)), None);
+        assert_eq!(exceeded(&limits, libc::SIGKILL, None), None);
+        assert_eq!(exceeded(&limits, libc::SIGSEGV, seconds(0)), None);
+        assert_eq!(exceeded(&limits, libc::SIGABRT, seconds(0)), None);
+        assert_eq!(exceeded(&limits, libc::SIGXFSZ, None), None);
+        assert_eq!(
+            exceeded(&[(Resource::FileSize, 1)], libc::SIGXFSZ, None),
+            Some(Resource::FileSize)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("AS", Some("512M")),
+            Ok((Resource::AddressSpace, 512 << 20))
+        );
+        assert_eq!(parse("CPU", Some(" 2 ")), Ok((Resource::CpuTime, 2)));
+        assert_eq!(parse("NOFILE", Some("64")), Ok((Resource::OpenFiles, 64)));
+        assert_eq!(parse("CORE", Some("0")), Ok((Resource::CoreSize, 0)));
+        assert!(parse("CPU", Some("2K")).is_err());
+        assert!(parse("RSS", Some("1G")).is_err());
+        assert!(parse("FSIZE", None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rlimit() {
+        use crate::{run, Language, Outcome};
+
+        let outcome = |program: &str| {
+            run(Language::C, program)
+                .unwrap()
+                .execute()
+                .unwrap()
+                .outcome()
+        };
+
+        assert_eq!(
+            outcome(
+                r#"
+                    #inline_c_rs RLIMIT: CPU=1
+
+                    int main() {
+                        for (volatile int i = 0; ; i++) {}
+                    }
+                "#
+            ),
+            Outcome::LimitExceeded(Resource::CpuTime)
+        );
+        assert_eq!(
+            outcome(
+                r#"
+                    #inline_c_rs RLIMIT: FSIZE=1K
+                    #include <stdio.h>
+
+                    int main() {
+                        FILE* file = tmpfile();
+
+                        for (int i = 0; i < 4096; i++) {
+                            fputc('x', file);
+                        }
+
+                        return fflush(file) == 0;
+                    }
+                "#
+            ),
+            Outcome::LimitExceeded(Resource::FileSize)
+        );
+
+        // The allocations fail once the address space is exhausted.
+        assert_eq!(
+            outcome(
+                r#"
+                    #inline_c_rs RLIMIT: AS=256M
+                    #include <stdlib.h>
+                    #include <string.h>
+
+                    int main() {
+                        size_t allocated = 0;
+                        char* block;
+
+                        while ((block = malloc(1 << 20)) != NULL) {
+                            memset(block, 1, 1 << 20);
+                            allocated++;
+                        }
+
+                        return allocated < 256 ? 3 : 0;
+                    }
+                "#
+            ),
+            Outcome::Exited(3)
+        );
+
+        // A crash is not blamed on a limit.
+        assert_eq!(
+            outcome(
+                r#"
+                    #inline_c_rs RLIMIT: AS=256M
+
+                    int main() {
+                        volatile int* pointer = 0;
+
+                        return *pointer;
+                    }
+                "#
+            ),
+            Outcome::Signaled(libc::SIGSEGV)
+        );
+
+        run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    int count = 0;
+
+                    while (fopen("/dev/null", "r") != NULL) {
+                        count++;
+                    }
+
+                    return count;
+                }
+            "#,
+        )
+        .unwrap()
+        .rlimit(Resource::OpenFiles, 8)
+        .failure()
+        .code(5);
+
+        let error = run(Language::C, "#inline_c_rs RLIMIT: RSS=1G\nint main() {}")
+            .err()
+            .expect("Unknown resources should be rejected");
+
+        assert!(error.to_string().contains("Unknown resource `RSS`"));
+    }
+}
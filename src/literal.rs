@@ -0,0 +1,309 @@
+use std::fmt::{self, Display, Write};
+
+/// Format a Rust value as a C literal. This is used by the `#{expr}`
+/// placeholders of [`assert_c`](crate::assert_c) and
+/// [`assert_cxx`](crate::assert_cxx).
+///
+/// Strings become string literals and byte arrays become initializer
+/// lists, both with proper escaping. Use [`Verbatim`] to insert a value
+/// as-is, e.g. a type name.
+pub trait CLiteral {
+    /// Return the C representation of `self`.
+    fn to_c_literal(&self) -> String;
+
+    /// Return the representation of `self` in the value of an
+    /// `#inline_c_rs` directive, which is used for the placeholders of
+    /// the directive lines. Strings only use the escapes of the directive
+    /// strings, and integers have no suffix. Other values are formatted
+    /// like in C.
+    fn to_directive_value(&self) -> String {
+        self.to_c_literal()
+    }
+}
+
+/// Insert the [`Display`] representation of a value in a C program
+/// without any escaping.
+pub struct Verbatim<T: Display>(pub T);
+
+impl<T: Display> CLiteral for Verbatim<T> {
+    fn to_c_literal(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl<T: CLiteral + ?Sized> CLiteral for &T {
+    fn to_c_literal(&self) -> String {
+        (**self).to_c_literal()
+    }
+
+    fn to_directive_value(&self) -> String {
+        (**self).to_directive_value()
+    }
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty => $suffix:expr),* $(,)?) => {
+        $(
+            impl CLiteral for $ty {
+                fn to_c_literal(&self) -> String {
+                    // The minimum of a signed type is not a literal, but
+                    // the negation of one, which is out of range.
+                    if *self == <$ty>::MIN && <$ty>::MIN != 0 {
+                        format!("({}{} - 1)", *self + 1, $suffix)
+                    } else {
+                        format!("{}{}", self, $suffix)
+                    }
+                }
+
+                fn to_directive_value(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(
+    i8 => "",
+    i16 => "",
+    i32 => "",
+    i64 => "LL",
+    isize => "LL",
+    u8 => "U",
+    u16 => "U",
+    u32 => "U",
+    u64 => "ULL",
+    usize => "ULL",
+);
+
+macro_rules! impl_float {
+    ($($ty:ty => $suffix:expr),* $(,)?) => {
+        $(
+            impl CLiteral for $ty {
+                fn to_c_literal(&self) -> String {
+                    if self.is_nan() {
+                        format!("(0.0{} / 0.0{})", $suffix, $suffix)
+                    } else if self.is_infinite() {
+                        format!("({}1.0{} / 0.0{})", if *self < 0.0 { "-" } else { "" }, $suffix, $suffix)
+                    } else {
+                        // `{:?}` always writes a decimal point or an exponent.
+                        format!("{:?}{}", self, $suffix)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float!(f32 => "f", f64 => "");
+
+impl CLiteral for bool {
+    fn to_c_literal(&self) -> String {
+        String::from(if *self { "1" } else { "0" })
+    }
+}
+
+impl CLiteral for char {
+    fn to_c_literal(&self) -> String {
+        if self.is_ascii() {
+            let mut output = String::from("'");
+            escape_byte(&mut output, *self as u8, '\'').unwrap();
+            output.push('\'');
+
+            output
+        } else {
+            // Not representable in a `char`, use the code point instead.
+            format!("{}", *self as u32)
+        }
+    }
+
+    fn to_directive_value(&self) -> String {
+        self.to_string().to_directive_value()
+    }
+}
+
+impl CLiteral for str {
+    fn to_c_literal(&self) -> String {
+        let mut output = String::from("\"");
+
+        for byte in self.bytes() {
+            escape_byte(&mut output, byte, '"').unwrap();
+        }
+
+        output.push('"');
+
+        output
+    }
+
+    fn to_directive_value(&self) -> String {
+        let mut output = String::from("\"");
+        let mut characters = self.chars().peekable();
+
+        // Whether the last character was written with a `\x` escape, or
+        // as a `?`.
+        let (mut after_hexadecimal, mut after_question) = (false, false);
+
+        while let Some(character) = characters.next() {
+            // A `\x` escape absorbs the following hexadecimal digits in C,
+            // and `\?` is not a directive escape, so the second `?` of a
+            // trigraph is written with `\x` too.
+            let hexadecimal = character.is_ascii_control()
+                || (after_hexadecimal && character.is_ascii_hexdigit())
+                || (after_question
+                    && character == '?'
+                    && matches!(
+                        characters.peek(),
+                        Some('=' | '(' | '/' | ')' | '\'' | '<' | '!' | '>' | '-')
+                    ));
+
+            match character {
+                '\\' => output.push_str("\\\\"),
+                '"' => output.push_str("\\\""),
+                '\n' => output.push_str("\\n"),
+                '\r' => output.push_str("\\r"),
+                '\t' => output.push_str("\\t"),
+                _ if hexadecimal => write!(output, "\\x{:02x}", character as u8).unwrap(),
+                _ => output.push(character),
+            }
+
+            after_hexadecimal = hexadecimal && !matches!(character, '\n' | '\r' | '\t');
+            after_question = character == '?' && !hexadecimal;
+        }
+
+        output.push('"');
+
+        output
+    }
+}
+
+impl CLiteral for String {
+    fn to_c_literal(&self) -> String {
+        self.as_str().to_c_literal()
+    }
+
+    fn to_directive_value(&self) -> String {
+        self.as_str().to_directive_value()
+    }
+}
+
+impl CLiteral for [u8] {
+    fn to_c_literal(&self) -> String {
+        let mut output = String::from("{");
+
+        for (nth, byte) in self.iter().enumerate() {
+            if nth > 0 {
+                output.push(',');
+            }
+
+            write!(output, " 0x{:02x}", byte).unwrap();
+        }
+
+        output.push_str(" }");
+
+        output
+    }
+}
+
+impl<const N: usize> CLiteral for [u8; N] {
+    fn to_c_literal(&self) -> String {
+        self[..].to_c_literal()
+    }
+}
+
+impl CLiteral for Vec<u8> {
+    fn to_c_literal(&self) -> String {
+        self.as_slice().to_c_literal()
+    }
+}
+
+/// Write a byte inside a C string or character literal delimited by
+/// `quote`. Bytes that are not printable ASCII are written with an octal
+/// escape, which, unlike `\x`, can never absorb the following characters.
+fn escape_byte(output: &mut String, byte: u8, quote: char) -> fmt::Result {
+    match byte {
+        b'\\' => output.write_str("\\\\"),
+        b'\n' => output.write_str("\\n"),
+        b'\r' => output.write_str("\\r"),
+        b'\t' => output.write_str("\\t"),
+        // Avoid trigraphs such as `??=`.
+        b'?' => output.write_str("\\?"),
+        byte if byte as char == quote => write!(output, "\\{}", quote),
+        0x20..=0x7e => output.write_char(byte as char),
+        byte => write!(output, "\\{:03o}", byte),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_literal() {
+        assert_eq!(
+            "a \"quoted\"\n\\ ??= é".to_c_literal(),
+            r#""a \"quoted\"\n\\ \?\?= \303\251""#
+        );
+    }
+
+    #[test]
+    fn test_directive_value() {
+        let value = "a \"quoted\"\n\\ ??= ?? é\0\x01b".to_directive_value();
+
+        assert_eq!(value, r#""a \"quoted\"\n\\ ?\x3f= ?? é\x00\x01\x62""#);
+        assert_eq!(
+            libafl_inline_c_directives::unquote(&value).as_deref(),
+            Some("a \"quoted\"\n\\ ??= ?? é\0\x01b")
+        );
+        assert_eq!(42u64.to_directive_value(), "42");
+        assert_eq!('a'.to_directive_value(), r#""a""#);
+    }
+
+    #[test]
+    fn test_byte_array_literal() {
+        assert_eq!(b"\x00\xffA".to_c_literal(), "{ 0x00, 0xff, 0x41 }");
+        assert_eq!(Vec::<u8>::new().to_c_literal(), "{ }");
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        assert_eq!(i8::MIN.to_c_literal(), "(-127 - 1)");
+        assert_eq!(i8::MAX.to_c_literal(), "127");
+        assert_eq!(i16::MIN.to_c_literal(), "(-32767 - 1)");
+        assert_eq!(i16::MAX.to_c_literal(), "32767");
+        assert_eq!(i32::MIN.to_c_literal(), "(-2147483647 - 1)");
+        assert_eq!(i32::MAX.to_c_literal(), "2147483647");
+        assert_eq!(i64::MIN.to_c_literal(), "(-9223372036854775807LL - 1)");
+        assert_eq!(i64::MAX.to_c_literal(), "9223372036854775807LL");
+        assert_eq!(
+            (isize::MIN as i64).to_c_literal(),
+            isize::MIN.to_c_literal()
+        );
+        assert_eq!(
+            (isize::MAX as i64).to_c_literal(),
+            isize::MAX.to_c_literal()
+        );
+        assert_eq!(u8::MIN.to_c_literal(), "0U");
+        assert_eq!(u8::MAX.to_c_literal(), "255U");
+        assert_eq!(u16::MIN.to_c_literal(), "0U");
+        assert_eq!(u16::MAX.to_c_literal(), "65535U");
+        assert_eq!(u32::MIN.to_c_literal(), "0U");
+        assert_eq!(u32::MAX.to_c_literal(), "4294967295U");
+        assert_eq!(u64::MIN.to_c_literal(), "0ULL");
+        assert_eq!(u64::MAX.to_c_literal(), "18446744073709551615ULL");
+        assert_eq!(
+            (usize::MAX as u64).to_c_literal(),
+            usize::MAX.to_c_literal()
+        );
+        assert_eq!(i64::MIN.to_directive_value(), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_scalar_literals() {
+        assert_eq!(42u64.to_c_literal(), "42ULL");
+        assert_eq!((-1i32).to_c_literal(), "-1");
+        assert_eq!(1.0f32.to_c_literal(), "1.0f");
+        assert_eq!('\''.to_c_literal(), r"'\''");
+        assert_eq!(true.to_c_literal(), "1");
+        assert_eq!(Verbatim("unsigned int").to_c_literal(), "unsigned int");
+    }
+}
@@ -1,12 +1,30 @@
-use crate::assert::Assert;
+use crate::{
+    assert::Assert,
+    batch, blocking,
+    compiler::{cxx_compiler, hermetic_args, Compiler, CompilerFamily, Requirement},
+    coverage::Instrumentation,
+    flags, forkserver,
+    limit::{self, Resource},
+    skip::Skipped,
+};
 use lazy_static::lazy_static;
+use libafl_inline_c_directives::{unquote, Directive, Value};
 use regex::Regex;
 use std::{
-    borrow::Cow, collections::HashMap, env, ffi::OsString, fmt, fmt::Display, io::prelude::*,
-    path::PathBuf, process::Command,
+    cell::RefCell,
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt,
+    fmt::Display,
+    future::Future,
+    io::prelude::*,
+    path::{Path, PathBuf},
+    process::{Command, Output},
 };
 
 #[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
 pub enum Language {
     C,
     Cxx,
@@ -24,27 +42,597 @@ impl Display for CompilationError {
 
 impl std::error::Error for CompilationError {}
 
-impl ToString for Language {
-    fn to_string(&self) -> String {
+/// Two builds of a differential execution behave differently.
+#[derive(Debug)]
+pub struct DifferentialError {
+    first: (String, Output),
+    second: (String, Output),
+}
+
+impl Display for DifferentialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (first_compiler, first) = &self.first;
+        let (second_compiler, second) = &self.second;
+
+        writeln!(
+            f,
+            "The builds of `{}` and `{}` behave differently:",
+            first_compiler, second_compiler
+        )?;
+        writeln!(f, "  status of `{}`: {}", first_compiler, first.status)?;
+        writeln!(f, "  status of `{}`: {}", second_compiler, second.status)?;
+        writeln!(
+            f,
+            "  stdout of `{}`: {:?}",
+            first_compiler,
+            String::from_utf8_lossy(&first.stdout)
+        )?;
+        writeln!(
+            f,
+            "  stdout of `{}`: {:?}",
+            second_compiler,
+            String::from_utf8_lossy(&second.stdout)
+        )?;
+        writeln!(
+            f,
+            "  stderr of `{}`: {:?}",
+            first_compiler,
+            String::from_utf8_lossy(&first.stderr)
+        )?;
+        write!(
+            f,
+            "  stderr of `{}`: {:?}",
+            second_compiler,
+            String::from_utf8_lossy(&second.stderr)
+        )
+    }
+}
+
+impl std::error::Error for DifferentialError {}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::C => String::from("c"),
-            Self::Cxx => String::from("cpp"),
+            Self::C => write!(f, "c"),
+            Self::Cxx => write!(f, "cpp"),
+        }
+    }
+}
+
+/// The configuration of the compilation, besides what the program sets
+/// with `#inline_c_rs` directives, which take precedence.
+///
+/// ```rust
+/// use libafl_inline_c::{Config, Language};
+///
+/// fn test_config() {
+///     Config::new()
+///         .compiler("clang-18")
+///         .hermetic(true)
+///         .compiler_requirement("clang>=15")
+///         .run(Language::C, "int main() { return 0; }")
+///         .unwrap()
+///         .success();
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Config {
+    include_directories: Vec<PathBuf>,
+    definitions: Vec<(String, Option<String>)>,
+    env_clear: bool,
+    env_removals: Vec<String>,
+    envs: Vec<(String, String)>,
+    limits: Vec<(Resource, u64)>,
+    fixtures: Vec<(PathBuf, Vec<u8>)>,
+    libraries: Vec<String>,
+    library_directories: Vec<PathBuf>,
+    objects: Vec<PathBuf>,
+    pkg_config_packages: Vec<String>,
+    target: Option<String>,
+    compiler: Option<String>,
+    cxx_compiler: Option<String>,
+    hermetic: bool,
+    compiler_requirement: Option<String>,
+    opt_level: Option<String>,
+    debug: bool,
+    source_coverage: bool,
+    forkserver: bool,
+    standard: Option<String>,
+    jobs: Option<usize>,
+    matrix: bool,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the target triple, like the `TARGET` directive.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Set the C compiler, like the `CC` directive, as a name such as
+    /// `clang-18` or as a full path such as `/opt/llvm/bin/clang`. It
+    /// overrides the discovery of `cc-rs`. Unless [`Config::cxx_compiler`]
+    /// is set, C++ programs are compiled with the matching C++ driver, e.g.
+    /// `clang++-18`.
+    pub fn compiler(&mut self, compiler: &str) -> &mut Self {
+        self.compiler = Some(compiler.to_string());
+        self
+    }
+
+    /// Set the C++ compiler, like the `CXX` directive.
+    pub fn cxx_compiler(&mut self, compiler: &str) -> &mut Self {
+        self.cxx_compiler = Some(compiler.to_string());
+        self
+    }
+
+    /// Enable the hermetic mode, like the `HERMETIC` directive. The
+    /// compiler must then be set explicitly, and the `CC`, `CXX`, `CFLAGS`
+    /// and `CXXFLAGS` environment variables read by `cc-rs` are ignored.
+    pub fn hermetic(&mut self, hermetic: bool) -> &mut Self {
+        self.hermetic = hermetic;
+        self
+    }
+
+    /// Require a minimum compiler version, like the `CC_MIN` directive,
+    /// e.g. `clang>=15` or `clang>=15 gcc>=12`. When the compiler does not
+    /// meet it, the program is skipped with a [`Skipped`] error.
+    pub fn compiler_requirement(&mut self, requirement: &str) -> &mut Self {
+        self.compiler_requirement = Some(requirement.to_string());
+        self
+    }
+
+    /// Set the optimization level, like the `OPT_LEVEL` directive: one of
+    /// `0`, `1`, `2`, `3`, `s` or `z`. It defaults to `0`.
+    pub fn opt_level(&mut self, opt_level: &str) -> &mut Self {
+        self.opt_level = Some(opt_level.to_string());
+        self
+    }
+
+    /// Generate debug information, like the `DEBUG` directive.
+    pub fn debug(&mut self, debug: bool) -> &mut Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Instrument the program for source coverage, like the
+    /// `SOURCE_COVERAGE` directive. See
+    /// [`Execution::coverage`][crate::Execution::coverage].
+    pub fn source_coverage(&mut self, source_coverage: bool) -> &mut Self {
+        self.source_coverage = source_coverage;
+        self
+    }
+
+    /// Set the language standard, like the `STD` directive, e.g. `c11`,
+    /// `gnu17` or `c++20`. It must match the language of the program.
+    pub fn standard(&mut self, standard: &str) -> &mut Self {
+        self.standard = Some(standard.to_string());
+        self
+    }
+
+    /// Add an include directory, like the `INCLUDE` directive.
+    pub fn include<P: AsRef<Path>>(&mut self, directory: P) -> &mut Self {
+        self.include_directories
+            .push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Define a preprocessor macro, like the `DEFINE` directive. The value
+    /// is passed as-is, and can contain spaces.
+    pub fn define<'a, V: Into<Option<&'a str>>>(&mut self, name: &str, value: V) -> &mut Self {
+        self.definitions
+            .push((name.to_string(), value.into().map(ToString::to_string)));
+        self
+    }
+
+    /// Link a library, like the `LINK` directive, e.g. `m` for `-lm` or
+    /// `m.lib` depending on the compiler.
+    pub fn link(&mut self, library: &str) -> &mut Self {
+        self.libraries.push(library.to_string());
+        self
+    }
+
+    /// Add a directory to search libraries in, like the `LINK_SEARCH`
+    /// directive.
+    pub fn link_search<P: AsRef<Path>>(&mut self, directory: P) -> &mut Self {
+        self.library_directories
+            .push(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Link a prebuilt object file or static library, like the `OBJECTS`
+    /// directive.
+    pub fn object<P: AsRef<Path>>(&mut self, object: P) -> &mut Self {
+        self.objects.push(object.as_ref().to_path_buf());
+        self
+    }
+
+    /// Compile and link with a package found by `pkg-config`, like the
+    /// `PKG_CONFIG` directive, e.g. `zlib`.
+    pub fn pkg_config(&mut self, package: &str) -> &mut Self {
+        self.pkg_config_packages.push(package.to_string());
+        self
+    }
+
+    /// Set an environment variable of the program, like the `ENV`
+    /// directive. The compiler does not see it.
+    pub fn env(&mut self, name: &str, value: &str) -> &mut Self {
+        self.envs.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Remove an environment variable inherited by the program, like the
+    /// `ENV_REMOVE` directive.
+    pub fn env_remove(&mut self, name: &str) -> &mut Self {
+        self.env_removals.push(name.to_string());
+        self
+    }
+
+    /// Run the program with an empty environment, besides the variables
+    /// set with [`Config::env`], like the `ENV_CLEAR` directive.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env_clear = true;
+        self
+    }
+
+    /// Limit a resource of the program, like the `RLIMIT` directive. See
+    /// [`Assert::rlimit`].
+    pub fn rlimit(&mut self, resource: Resource, limit: u64) -> &mut Self {
+        self.limits.push((resource, limit));
+        self
+    }
+
+    /// Write a file in the working directory of the program before it
+    /// runs, like the `FIXTURE` directive. See [`Assert::fixture`].
+    pub fn fixture<P: AsRef<Path>, C: AsRef<[u8]>>(&mut self, path: P, contents: C) -> &mut Self {
+        self.fixtures
+            .push((path.as_ref().to_path_buf(), contents.as_ref().to_vec()));
+        self
+    }
+
+    /// Link the forkserver runtime, like the `FORKSERVER` directive. See
+    /// [`Assert::forkserver`].
+    pub fn forkserver(&mut self, forkserver: bool) -> &mut Self {
+        self.forkserver = forkserver;
+        self
+    }
+
+    /// Compile at most `jobs` programs at once with [`Config::run_batch`].
+    /// It defaults to the available parallelism.
+    pub fn jobs(&mut self, jobs: usize) -> &mut Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Compile the program with this configuration.
+    pub fn run(
+        &self,
+        language: Language,
+        program: &str,
+    ) -> Result<Assert, Box<dyn std::error::Error>> {
+        run_with(language, program, self).map_err(|error| error as _)
+    }
+
+    /// Like [`Config::run`], but compile the program in another thread.
+    /// See [`run_async`].
+    pub fn run_async(
+        &self,
+        language: Language,
+        program: &str,
+    ) -> impl Future<Output = Result<Assert, BoxError>> + Send + 'static {
+        let config = self.clone();
+        let program = program.to_string();
+
+        blocking::spawn(move || run_with(language, &program, &config))
+    }
+
+    /// Compile the snippets concurrently with this configuration. See
+    /// [`run_batch`].
+    pub fn run_batch<I, S>(&self, snippets: I) -> Vec<Result<Assert, BoxError>>
+    where
+        I: IntoIterator<Item = (Language, S)>,
+        S: AsRef<str>,
+    {
+        batch::run(
+            self,
+            snippets
+                .into_iter()
+                .map(|(language, program)| (language, program.as_ref().to_string()))
+                .collect(),
+            self.jobs,
+        )
+    }
+
+    /// Install this configuration for the programs compiled by the
+    /// current thread with the macros, until the guard is dropped. The
+    /// previously installed configuration is then restored.
+    ///
+    /// ```rust
+    /// use libafl_inline_c::{assert_c, Config};
+    ///
+    /// fn test_installed_config() {
+    ///     let _guard = Config::new().define("GREETING", "\"Hello\"").install();
+    ///
+    ///     (assert_c! {
+    ///         #include <stdio.h>
+    ///
+    ///         int main() {
+    ///             printf("%s\n", GREETING);
+    ///
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .success()
+    ///     .stdout("Hello\n");
+    /// }
+    /// ```
+    pub fn install(&self) -> ConfigGuard {
+        ConfigGuard {
+            previous: CONFIG.with(|config| config.borrow_mut().replace(self.clone())),
         }
     }
+
+    /// The configuration installed by the current thread, if any.
+    pub(crate) fn current() -> Self {
+        CONFIG.with(|config| config.borrow().clone().unwrap_or_default())
+    }
+
+    /// The settings set by the configuration, with the name of the
+    /// matching directive.
+    pub(crate) fn settings(&self) -> HashMap<String, String> {
+        [
+            ("TARGET", &self.target),
+            ("CC", &self.compiler),
+            ("CXX", &self.cxx_compiler),
+            ("CC_MIN", &self.compiler_requirement),
+            ("OPT_LEVEL", &self.opt_level),
+            ("STD", &self.standard),
+        ]
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.as_ref().cloned()?)))
+        .collect()
+    }
+}
+
+thread_local! {
+    /// The configuration installed by the current thread, with
+    /// [`Config::install`] or by a `#[libafl_inline_c::test]` matrix.
+    static CONFIG: RefCell<Option<Config>> = const { RefCell::new(None) };
+}
+
+/// Restores the previously installed configuration when dropped. See
+/// [`Config::install`].
+#[must_use = "the configuration is uninstalled when the guard is dropped"]
+pub struct ConfigGuard {
+    previous: Option<Config>,
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        CONFIG.with(|config| *config.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Compile the programs run by the current thread with the given target,
+/// compiler and optimization level, on top of the installed
+/// configuration, until the guard is dropped. This is used by the tests
+/// generated by `#[libafl_inline_c::test]`.
+#[doc(hidden)]
+pub fn install_matrix(
+    target: Option<&str>,
+    compiler: Option<&str>,
+    opt_level: Option<&str>,
+) -> ConfigGuard {
+    let mut config = Config::current();
+
+    if let Some(target) = target {
+        config.target(target);
+    }
+
+    if let Some(compiler) = compiler {
+        config.compiler(compiler);
+        config.cxx_compiler = None;
+    }
+
+    if let Some(opt_level) = opt_level {
+        config.opt_level(opt_level);
+    }
+
+    config.matrix = true;
+    config.install()
 }
 
 #[doc(hidden)]
 pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn std::error::Error>> {
-    let (program, variables) = collect_environment_variables(program);
-    let (program, options) = collect_options(&program);
+    run_with(language, program, &Config::current()).map_err(|error| error as _)
+}
+
+/// Compile many snippets concurrently, and return one result per
+/// snippet, in the same order. The configuration installed by the current
+/// thread applies.
+///
+/// At most [`Config::jobs`] snippets are compiled at once, which defaults
+/// to the available parallelism. When cargo or make provide a jobserver,
+/// e.g. with `CARGO_MAKEFLAGS`, each compilation besides the first one
+/// also waits for one of its tokens, so that parallel tests do not
+/// oversubscribe the CPUs.
+///
+/// ```rust
+/// use libafl_inline_c::{run_batch, Language};
+///
+/// fn test_run_batch() {
+///     let results = run_batch(vec![
+///         (Language::C, "int main() { return 0; }"),
+///         (Language::Cxx, "int main() { return 0; }"),
+///     ]);
+///
+///     for result in results {
+///         result.unwrap().success();
+///     }
+/// }
+/// ```
+pub fn run_batch<I, S>(snippets: I) -> Vec<Result<Assert, BoxError>>
+where
+    I: IntoIterator<Item = (Language, S)>,
+    S: AsRef<str>,
+{
+    Config::current().run_batch(snippets)
+}
+
+/// Like `run`, but compile the program in another thread, so that the
+/// threads of the executor are not blocked. It works with any executor.
+/// The configuration installed by the current thread is read when it is
+/// called, not when the future is polled.
+///
+/// ```rust
+/// use libafl_inline_c::{run_async, Language};
+///
+/// async fn test_run_async() {
+///     let execution = run_async(Language::C, "int main() { return 0; }")
+///         .await
+///         .unwrap()
+///         .execute_async()
+///         .await
+///         .unwrap();
+///
+///     assert!(execution.success());
+/// }
+/// ```
+pub fn run_async(
+    language: Language,
+    program: &str,
+) -> impl Future<Output = Result<Assert, BoxError>> + Send + 'static {
+    Config::current().run_async(language, program)
+}
+
+/// Like [`run`], but for a program read from the file at `path`. The
+/// directory of the file is added to the include paths, and compiler
+/// diagnostics refer to the file.
+#[doc(hidden)]
+pub fn run_file(
+    language: Language,
+    path: &str,
+    program: &str,
+) -> Result<Assert, Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    let mut config = Config::current();
+    config
+        .include_directories
+        .extend(path.parent().map(Path::to_path_buf));
+    let program = format!(
+        "#line 1 \"{}\"\n{}",
+        path.display().to_string().replace('\\', "\\\\"),
+        program
+    );
+
+    run_with(language, &program, &config).map_err(|error| error as _)
+}
+
+pub(crate) fn run_with(
+    language: Language,
+    program: &str,
+    config: &Config,
+) -> Result<Assert, BoxError> {
+    let (program, directives) = libafl_inline_c_directives::parse(program)?;
+    let (config, variables, options) = resolve(config, env::vars(), directives)?;
+
+    if options.contains(&"DIFFERENTIAL".to_string()) {
+        run_differential(&language, &program, &variables, &options, &config)
+    } else {
+        compile(&language, &program, &variables, &options, &config)
+    }
+}
+
+/// Compile the program with every available compiler, run each build, and
+/// check that they all behave the same. The [`Assert`] of the first build
+/// is returned.
+fn run_differential(
+    language: &Language,
+    program: &str,
+    variables: &HashMap<String, String>,
+    options: &[String],
+    config: &Config,
+) -> Result<Assert, BoxError> {
+    let compilers = variables
+        .get("DIFFERENTIAL_COMPILERS")
+        .map(|compilers| {
+            compilers
+                .split_ascii_whitespace()
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_else(|| vec![String::from("gcc"), String::from("clang")]);
+
+    let mut builds: Vec<(String, Assert, Output)> = Vec::new();
+
+    for compiler in compilers {
+        let executable = match language {
+            Language::C => PathBuf::from(&compiler),
+            Language::Cxx => cxx_compiler(&compiler),
+        };
+
+        // Compilers that are not installed are skipped.
+        if Command::new(executable).arg("--version").output().is_err() {
+            continue;
+        }
+
+        let mut variables = variables.clone();
+        variables.insert(String::from("CC"), compiler.clone());
+        variables.remove("CXX");
+
+        let mut assert = compile(language, program, &variables, options, config)?;
+        let output = assert.output()?;
+
+        if let Some((first_compiler, _, first_output)) = builds.first() {
+            if (&output.status, &output.stdout, &output.stderr)
+                != (
+                    &first_output.status,
+                    &first_output.stdout,
+                    &first_output.stderr,
+                )
+            {
+                return Err(Box::new(DifferentialError {
+                    first: (first_compiler.clone(), first_output.clone()),
+                    second: (compiler, output),
+                }));
+            }
+        }
+
+        builds.push((compiler, assert, output));
+    }
+
+    match builds.into_iter().next() {
+        Some((_, assert, _)) => Ok(assert),
+        None => Err(Box::new(CompilationError(String::from(
+            "None of the compilers of the differential execution is available",
+        )))),
+    }
+}
+
+fn compile(
+    language: &Language,
+    program: &str,
+    variables: &HashMap<String, String>,
+    options: &[String],
+    config: &Config,
+) -> Result<Assert, BoxError> {
     let is_shared = options.contains(&"SHARED".to_string());
+    let hermetic = config.hermetic || options.contains(&"HERMETIC".to_string());
+    let debug = config.debug || options.contains(&"DEBUG".to_string());
+    let source_coverage =
+        config.source_coverage || options.contains(&"SOURCE_COVERAGE".to_string());
+    let forkserver = config.forkserver || options.contains(&"FORKSERVER".to_string());
+    let opt_level = variables.get("OPT_LEVEL").map_or("0", String::as_str);
 
-    //println!("{}", program);
-    let mut program_file = tempfile::Builder::new()
-        .prefix("inline-c-rs-")
-        .suffix(&format!(".{}", language.to_string()))
-        .tempfile()?;
-    program_file.write_all(program.as_bytes())?;
+    if !["0", "1", "2", "3", "s", "z"].contains(&opt_level) {
+        return Err(Box::new(CompilationError(format!(
+            "Invalid optimization level `{}`, expected one of 0, 1, 2, 3, s or z",
+            opt_level
+        ))));
+    }
 
     let host = target_lexicon::HOST.to_string();
 
@@ -55,62 +643,238 @@ pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn std::err
 
     let msvc = target.contains("msvc");
 
-    let (_, input_path) = program_file.keep()?;
-    let mut output_temp = tempfile::Builder::new();
-    let output_temp = output_temp.prefix("inline-c-rs-");
-
-    if target.contains("windows") && is_shared {
-        //this is to encompass both msvc + mingw
-        output_temp.suffix(".dll");
-    } else if target.contains("windows") {
-        output_temp.suffix(".exe");
-    }
-
-    let (_, output_path) = output_temp.tempfile()?.keep()?;
-
     let mut build = cc::Build::new();
     let mut build = build
         .cargo_metadata(false)
+        .cargo_warnings(false)
         .warnings(true)
         .extra_warnings(true)
-        .debug(false)
+        .debug(debug)
         .host(&host)
         .target(&target)
-        .opt_level(0);
+        .opt_level_str(opt_level);
 
     if let Language::Cxx = language {
         build = build.cpp(true);
     }
 
+    // A C compiler also selects the matching C++ driver.
+    let c_compiler = variables.get("CC");
+    let compiler_path = match language {
+        Language::C => c_compiler.map(PathBuf::from),
+        Language::Cxx => variables
+            .get("CXX")
+            .map(PathBuf::from)
+            .or_else(|| c_compiler.map(|compiler| cxx_compiler(compiler))),
+    };
+
+    match compiler_path {
+        Some(compiler_path) => build = build.compiler(compiler_path),
+        None if hermetic => {
+            return Err(Box::new(CompilationError(String::from(
+                "The hermetic mode requires the compiler to be set with `CC` or `CXX`",
+            ))))
+        }
+        None => (),
+    }
+
     // Usually, `cc-rs` is used to produce libraries. In our case, we
     // want to produce an (executable) object file. The following code
     // is kind of a hack around `cc-rs`. It avoids the addition of the
     // `-c` argument on the compiler, and manually adds other
     // arguments.
 
-    let compiler = build.try_get_compiler()?;
+    // The compilers of a `#[libafl_inline_c::test]` matrix may not be
+    // installed, which skips the programs that need them.
+    let compiler = match build.try_get_compiler() {
+        Ok(compiler) => compiler,
+        Err(error) if config.matrix => {
+            return Err(Box::new(Skipped::new(format!(
+                "no compiler found for `{}`: {}",
+                target, error
+            ))))
+        }
+        Err(error) => return Err(Box::new(error)),
+    };
+
+    if config.matrix
+        && Command::new(compiler.path())
+            .arg("--version")
+            .output()
+            .is_err()
+    {
+        return Err(Box::new(Skipped::new(format!(
+            "the compiler `{}` for `{}` is not available",
+            compiler.path().display(),
+            target
+        ))));
+    }
+
+    let detected_compiler = Compiler::detect(&compiler);
+
+    if let Some(requirement) = variables.get("CC_MIN") {
+        if !Requirement::parse(requirement)
+            .map_err(CompilationError)?
+            .is_met_by(&detected_compiler)
+        {
+            return Err(Box::new(Skipped::new(format!(
+                "`{}` does not meet the compiler requirement `{}`",
+                detected_compiler.path().display(),
+                requirement
+            ))));
+        }
+    }
+
+    // The hermetic mode does not use the arguments of `cc-rs`, which
+    // include the `CFLAGS` or `CXXFLAGS` of the environment.
+    let mut compiler_args = if hermetic {
+        hermetic_args(&compiler, opt_level, debug, &host, &target)
+    } else {
+        compiler.args().to_vec()
+    };
+
+    if let Some(standard) = variables.get("STD") {
+        compiler_args.push(standard_flag(language, standard, compiler.is_like_msvc())?.into());
+    }
+
+    let mut compiler_flags = ["CFLAGS", "CPPFLAGS", "CXXFLAGS"]
+        .iter()
+        .map(|name| env_flags(variables, name))
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+
+    if source_coverage {
+        compiler_flags.extend(
+            Instrumentation::flags(if compiler.is_like_msvc() {
+                CompilerFamily::Msvc
+            } else {
+                detected_compiler.family()
+            })
+            .map_err(CompilationError)?
+            .iter()
+            .map(|flag| flag.to_string()),
+        );
+
+        if let Some(tool) =
+            Instrumentation::missing_tool(detected_compiler.family(), detected_compiler.path())
+        {
+            return Err(Box::new(Skipped::new(format!(
+                "`SOURCE_COVERAGE` requires `{}`, which is not installed",
+                tool
+            ))));
+        }
+    }
+    let mut libraries = config
+        .libraries
+        .iter()
+        .map(|library| flags::library(library, msvc))
+        .collect::<Vec<_>>();
+
+    if !config.pkg_config_packages.is_empty() {
+        compiler_flags.extend(pkg_config(&config.pkg_config_packages, "--cflags")?);
+        libraries.extend(flags::linker_arguments(
+            &pkg_config(&config.pkg_config_packages, "--libs")?,
+            msvc,
+        ));
+    }
+
+    // Like `make`, the `LDFLAGS` come first, then the objects, and then
+    // the libraries, which only resolve the symbols used before them.
+    let mut linker_arguments = flags::linker_arguments(&env_flags(variables, "LDFLAGS")?, msvc);
+    linker_arguments.extend(config.library_directories.iter().map(|directory| {
+        if msvc {
+            format!("/LIBPATH:{}", directory.display())
+        } else {
+            format!("-L{}", directory.display())
+        }
+    }));
+
+    // `cl` only passes the debug information of the object file to the
+    // linker when asked to.
+    if msvc && debug {
+        linker_arguments.push(String::from("/DEBUG"));
+    }
+
+    //println!("{}", program);
+    let mut program_file = tempfile::Builder::new()
+        .prefix("inline-c-rs-")
+        .suffix(&format!(".{}", language))
+        .tempfile()?;
+    program_file.write_all(program.as_bytes())?;
+
+    let (_, input_path) = program_file.keep()?;
+
+    // The runtime is compiled along with the program, in its language.
+    let runtime_path = if forkserver {
+        if is_shared || target.contains("windows") {
+            return Err(Box::new(CompilationError(String::from(
+                "`FORKSERVER` requires an executable for a Unix target",
+            ))));
+        }
+
+        let mut runtime_file = tempfile::Builder::new()
+            .prefix("inline-c-rs-forkserver-")
+            .suffix(&format!(".{}", language))
+            .tempfile()?;
+        runtime_file.write_all(forkserver::RUNTIME.as_bytes())?;
+
+        Some(runtime_file.keep()?.1)
+    } else {
+        None
+    };
+    let mut output_temp = tempfile::Builder::new();
+    let output_temp = output_temp.prefix("inline-c-rs-");
+
+    if target.contains("windows") && is_shared {
+        //this is to encompass both msvc + mingw
+        output_temp.suffix(".dll");
+    } else if target.contains("windows") {
+        output_temp.suffix(".exe");
+    }
+
+    let (_, output_path) = output_temp.tempfile()?.keep()?;
+
     let mut command;
 
     if msvc {
-        command = compiler.to_command();
+        let tool_command = compiler.to_command();
+
+        command = Command::new(tool_command.get_program());
+        command.args(&compiler_args);
+        command.envs(
+            tool_command
+                .get_envs()
+                .filter_map(|(key, value)| Some((key, value?))),
+        );
 
-        command_add_compiler_flags(&mut command, &variables, is_shared, &target);
+        command_add_compiler_flags(&mut command, config, &compiler_flags, is_shared, &target);
         command_add_output_file(&mut command, &output_path, msvc, compiler.is_like_clang());
         command.arg(input_path.clone());
-        command.envs(variables.clone());
+        command.args(&config.objects);
+
+        // Everything after `/link` is passed to the linker.
+        if !linker_arguments.is_empty() || !libraries.is_empty() {
+            command
+                .arg("/link")
+                .args(&linker_arguments)
+                .args(&libraries);
+        }
     } else {
         command = Command::new(compiler.path());
 
         command.arg(input_path.clone()); // the input must come first
-        command.args(compiler.args());
+        command.args(&runtime_path);
+        command.args(&compiler_args);
 
-        command_add_compiler_flags(&mut command, &variables, is_shared, &target);
+        command_add_compiler_flags(&mut command, config, &compiler_flags, is_shared, &target);
+        command.args(&linker_arguments);
+        command.args(&config.objects);
+        command.args(&libraries);
         command_add_output_file(&mut command, &output_path, msvc, compiler.is_like_clang());
     }
 
-    command.envs(variables.clone());
-
     let mut files_to_remove = vec![input_path.clone(), output_path.clone()];
+    files_to_remove.extend(runtime_path);
     if msvc {
         let mut intermediate_path = output_path.clone();
         intermediate_path.set_extension("obj");
@@ -125,61 +889,262 @@ pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn std::err
         )));
     }
 
-    let mut command = Command::new(output_path.clone());
-    command.envs(variables);
+    let instrumentation = if source_coverage {
+        let instrumentation = Instrumentation::new(
+            detected_compiler.family(),
+            detected_compiler.path(),
+            &input_path,
+            &output_path,
+        )?;
+        files_to_remove.extend(instrumentation.files());
 
-    Ok(Assert::new(command, Some(files_to_remove), output_path))
-}
+        Some(instrumentation)
+    } else {
+        None
+    };
 
-fn collect_environment_variables<'p>(program: &'p str) -> (Cow<'p, str>, HashMap<String, String>) {
-    const ENV_VAR_PREFIX: &str = "INLINE_C_RS_";
+    let mut assert = Assert::new(
+        Command::new(output_path.clone()),
+        Some(files_to_remove),
+        output_path,
+        detected_compiler,
+        is_shared,
+    );
 
-    lazy_static! {
-        static ref REGEX: Regex = Regex::new(
-            r#"#inline_c_rs (?P<variable_name>[^:\n\r]+):\s*"(?P<variable_value>[^"]+)""#
-        )
-        .unwrap();
+    if let Some(instrumentation) = instrumentation {
+        assert.instrument(instrumentation);
+    }
+
+    if config.env_clear || options.contains(&"ENV_CLEAR".to_string()) {
+        assert.env_clear();
+    }
+
+    for name in &config.env_removals {
+        assert.env_remove(name);
+    }
+
+    for (name, value) in &config.envs {
+        assert.env(name, value);
+    }
+
+    for (resource, limit) in &config.limits {
+        assert.rlimit(*resource, *limit);
+    }
+
+    for (path, contents) in &config.fixtures {
+        assert.fixture(path, contents);
     }
 
+    Ok(assert)
+}
+
+/// The settings that can be set with `#inline_c_rs NAME: "value"`, or with
+/// the `INLINE_C_RS_NAME` environment variable.
+const SETTINGS: &[&str] = &[
+    "TARGET",
+    "CC",
+    "CXX",
+    "CC_MIN",
+    "CFLAGS",
+    "CPPFLAGS",
+    "CXXFLAGS",
+    "LDFLAGS",
+    "OPT_LEVEL",
+    "STD",
+    "DIFFERENTIAL_COMPILERS",
+];
+
+const ENV_VAR_PREFIX: &str = "INLINE_C_RS_";
+
+/// The settings of a compilation, by name, e.g. `CC`.
+type Settings = HashMap<String, String>;
+
+/// An error that can be sent to another thread, e.g. from [`run_async`].
+pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Resolve the configuration of a compilation. From the lowest
+/// precedence to the highest, it is made of the `INLINE_C_RS_*`
+/// environment variables, which the hermetic mode ignores, the given
+/// configuration, and the directives of the program. A layer setting `CC` without `CXX` also discards the `CXX`
+/// of the layers below, so that the matching C++ driver is used. The
+/// settings, such as `CC`, are returned with their directive name, and the
+/// enabled flags, such as `SHARED`, are returned as options.
+fn resolve(
+    config: &Config,
+    environment: impl IntoIterator<Item = (String, String)>,
+    directives: Vec<Directive>,
+) -> Result<(Config, Settings, Vec<String>), CompilationError> {
+    let mut config = config.clone();
     let mut variables = HashMap::new();
+    let mut envs = Vec::new();
+    let hermetic = config.hermetic
+        || directives
+            .iter()
+            .rev()
+            .find(|directive| directive.name == "HERMETIC")
+            .is_some_and(|directive| directive.value == Value::Bool(true));
 
-    for (variable_name, variable_value) in env::vars().filter_map(|(mut name, value)| {
-        if name.starts_with(ENV_VAR_PREFIX) {
-            Some((name.split_off(ENV_VAR_PREFIX.len()), value))
-        } else {
-            None
+    for (name, value) in environment.into_iter().filter(|_| !hermetic) {
+        if let Some(name) = name.strip_prefix(ENV_VAR_PREFIX) {
+            // `INLINE_C_RS_ENV_NAME` sets `NAME` in the environment of the
+            // program.
+            if let Some(name) = name.strip_prefix("ENV_") {
+                envs.push((name.to_string(), value));
+            } else if SETTINGS.contains(&name) {
+                variables.insert(name.to_string(), value);
+            }
         }
-    }) {
-        variables.insert(variable_name, variable_value);
     }
 
-    for captures in REGEX.captures_iter(program) {
-        variables.insert(
-            captures["variable_name"].trim().to_string(),
-            captures["variable_value"].to_string(),
-        );
+    // The variables set later take precedence.
+    config.envs.splice(0..0, envs);
+    layer(&mut variables, config.settings());
+
+    let (settings, options) = apply_directives(directives, &mut config)?;
+    layer(&mut variables, settings);
+
+    Ok((config, variables, options))
+}
+
+/// Override the settings with those of a layer of higher precedence.
+fn layer(settings: &mut HashMap<String, String>, layer: HashMap<String, String>) {
+    if layer.contains_key("CC") && !layer.contains_key("CXX") {
+        settings.remove("CXX");
     }
 
-    let program = REGEX.replace_all(program, "");
+    settings.extend(layer);
+}
 
-    (program, variables)
+/// Apply the directives of a program. The settings, such as `CC`, are
+/// returned, and the enabled flags, such as `SHARED`, are returned as
+/// options. The other directives, which can be repeated, extend the
+/// configuration. The settings configure the build only, and never reach
+/// the environment of the program.
+fn apply_directives(
+    directives: Vec<Directive>,
+    config: &mut Config,
+) -> Result<(Settings, Vec<String>), CompilationError> {
+    let mut variables = HashMap::new();
+    let mut options = Vec::new();
+
+    for Directive { name, value, .. } in directives {
+        match (name.as_str(), value) {
+            (_, Value::Bool(true)) => options.push(name),
+            (_, Value::Bool(false)) => options.retain(|option| *option != name),
+            (_, Value::String(value)) => {
+                variables.insert(name, value);
+            }
+            ("DEFINE", Value::KeyValue(name, value)) => {
+                config.define(&name, value.as_deref());
+            }
+            // The value is the rest of the line, or a string.
+            ("ENV", Value::KeyValue(name, value)) => {
+                let value = value.unwrap_or_default();
+
+                config.env(&name, &unquote(&value).unwrap_or(value));
+            }
+            ("RLIMIT", Value::KeyValue(name, value)) => {
+                let (resource, limit) =
+                    limit::parse(&name, value.as_deref()).map_err(CompilationError)?;
+
+                config.rlimit(resource, limit);
+            }
+            ("FIXTURE", Value::Named(path, contents)) => {
+                config.fixture(path, contents);
+            }
+            ("INCLUDE", Value::List(directories)) => {
+                for directory in directories {
+                    config.include(directory);
+                }
+            }
+            ("LINK_SEARCH", Value::List(directories)) => {
+                for directory in directories {
+                    config.link_search(directory);
+                }
+            }
+            ("OBJECTS", Value::List(objects)) => {
+                for object in objects {
+                    config.object(object);
+                }
+            }
+            ("LINK", Value::List(libraries)) => {
+                for library in words(&libraries) {
+                    config.link(library);
+                }
+            }
+            ("PKG_CONFIG", Value::List(packages)) => {
+                for package in words(&packages) {
+                    config.pkg_config(package);
+                }
+            }
+            ("ENV_REMOVE", Value::List(variables)) => {
+                for variable in words(&variables) {
+                    config.env_remove(variable);
+                }
+            }
+            (_, Value::List(list)) => {
+                variables.insert(name, words(&list).collect::<Vec<_>>().join(" "));
+            }
+            (_, Value::KeyValue(..)) => unreachable!("`{}` is not a key/value directive", name),
+            (_, Value::Named(..)) => unreachable!("`{}` is not a named directive", name),
+        }
+    }
+
+    Ok((variables, options))
+}
+
+/// Split the strings of a list on whitespace, e.g. `["m pthread", "dl"]`
+/// into `m`, `pthread` and `dl`.
+fn words(list: &[String]) -> impl Iterator<Item = &str> {
+    list.iter().flat_map(|words| words.split_whitespace())
 }
 
-fn collect_options<'p>(program: &'p str) -> (Cow<'p, str>, Vec<String>) {
+/// Return the flag selecting the language standard, e.g. `-std=c11` or
+/// `/std:c11`.
+fn standard_flag(
+    language: &Language,
+    standard: &str,
+    msvc: bool,
+) -> Result<String, CompilationError> {
     lazy_static! {
-        static ref REGEX_NO_VAL: Regex =
-            Regex::new(r#"#inline_c_rs (?P<variable_name>\w+)"#).unwrap();
+        static ref C: Regex = Regex::new(r"^(c|gnu)(89|90|99|9x|11|1x|17|18|2x|23)$").unwrap();
+        static ref CXX: Regex =
+            Regex::new(r"^(c|gnu)\+\+(98|03|0x|11|1y|14|1z|17|2a|20|2b|23|2c|26)$").unwrap();
     }
 
-    let mut options = Vec::new();
+    let valid = match language {
+        Language::C => C.is_match(standard),
+        Language::Cxx => CXX.is_match(standard),
+    };
 
-    for captures in REGEX_NO_VAL.captures_iter(&program) {
-        options.push(captures["variable_name"].trim().to_string());
+    if !valid {
+        return Err(CompilationError(format!(
+            "Invalid standard `{}` for {}, expected e.g. {}",
+            standard,
+            match language {
+                Language::C => "C",
+                Language::Cxx => "C++",
+            },
+            match language {
+                Language::C => "`c11` or `gnu17`",
+                Language::Cxx => "`c++17` or `gnu++20`",
+            }
+        )));
     }
 
-    let program = REGEX_NO_VAL.replace_all(&program, "");
+    if msvc {
+        // MSVC has no GNU dialects, and only knows some standards.
+        if standard.starts_with("gnu") {
+            return Err(CompilationError(format!(
+                "The standard `{}` is not supported by MSVC",
+                standard
+            )));
+        }
 
-    (program, options)
+        Ok(format!("/std:{}", standard))
+    } else {
+        Ok(format!("-std={}", standard))
+    }
 }
 
 // This is copy-pasted and edited from `cc-rs`.
@@ -200,30 +1165,80 @@ fn command_add_output_file(command: &mut Command, output_path: &PathBuf, msvc: b
     }
 }
 
+/// Return the flags set by the `name` directive or the `INLINE_C_RS_<name>`
+/// environment variable, split like a shell would.
+fn env_flags(
+    variables: &HashMap<String, String>,
+    name: &str,
+) -> Result<Vec<String>, CompilationError> {
+    match variables.get(name) {
+        Some(value) => flags::split(name, value).map_err(CompilationError),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Return the `--cflags` or the `--libs` of packages, as found by
+/// `pkg-config` or the program set in the `PKG_CONFIG` environment
+/// variable.
+fn pkg_config(packages: &[String], kind: &str) -> Result<Vec<String>, BoxError> {
+    let pkg_config = env::var_os("PKG_CONFIG").unwrap_or_else(|| OsString::from("pkg-config"));
+    let output = Command::new(&pkg_config)
+        .arg(kind)
+        .args(packages)
+        .output()
+        .map_err(|error| {
+            CompilationError(format!(
+                "Failed to run `{}`: {}",
+                pkg_config.to_string_lossy(),
+                error
+            ))
+        })?;
+
+    if !output.status.success() {
+        return Err(Box::new(CompilationError(format!(
+            "`pkg-config` failed to find `{}`:\n{}",
+            packages.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ))));
+    }
+
+    Ok(flags::split(kind, &String::from_utf8_lossy(&output.stdout)).map_err(CompilationError)?)
+}
+
 fn command_add_compiler_flags(
     command: &mut Command,
-    variables: &HashMap<String, String>,
+    config: &Config,
+    compiler_flags: &[String],
     is_shared: bool,
-    target: &String,
+    target: &str,
 ) {
-    let get_env_flags = |env_name: &str| -> Vec<String> {
-        variables
-            .get(env_name)
-            .map(|e| e.to_string())
-            .ok_or_else(|| env::var(env_name))
-            .unwrap_or_default()
-            .split_ascii_whitespace()
-            .map(|slice| slice.to_string())
-            .collect()
-    };
+    let msvc = target.contains("windows") && target.contains("msvc");
+
+    // Each directory and definition is a single argument, so that they
+    // can contain spaces.
+    for include_directory in &config.include_directories {
+        command
+            .arg(if msvc { "/I" } else { "-I" })
+            .arg(include_directory);
+    }
+
+    for (name, value) in &config.definitions {
+        let mut definition = OsString::from(if msvc { "/D" } else { "-D" });
+        definition.push(name);
 
-    command.args(get_env_flags("CFLAGS"));
-    command.args(get_env_flags("CPPFLAGS"));
-    command.args(get_env_flags("CXXFLAGS"));
+        if let Some(value) = value {
+            definition.push("=");
+            definition.push(value);
+        }
+
+        command.arg(definition);
+    }
+
+    command.args(compiler_flags);
 
     if is_shared {
         //are we trying to create a shared library
-        if target.contains("windows") && target.contains("msvc") {
+        if msvc {
             //msvc
             command.arg("/LD");
         } else {
@@ -231,10 +1246,6 @@ fn command_add_compiler_flags(
             command.arg("-shared");
         }
     }
-
-    for linker_argument in get_env_flags("LDFLAGS") {
-        command.arg(format!("-Wl,{}", linker_argument));
-    }
 }
 
 #[cfg(test)]
@@ -261,6 +1272,581 @@ mod tests {
         .stdout(predicate::eq("Hello, World!\n").normalize());
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_run_differential() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs DIFFERENTIAL
+                #inline_c_rs DIFFERENTIAL_COMPILERS: "cc gcc inline-c-rs-missing-compiler"
+                #include <stdio.h>
+
+                int main() {
+                    printf("Hello, World!\n");
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout(predicate::eq("Hello, World!\n").normalize());
+
+        crate::skippable(|| {
+            // The mismatch needs two different compilers.
+            if ["gcc", "clang"]
+                .iter()
+                .any(|compiler| Command::new(compiler).arg("--version").output().is_err())
+            {
+                crate::fail(Box::new(Skipped::new(String::from(
+                    "`gcc` or `clang` is not installed",
+                ))));
+            }
+
+            let error = run(
+                Language::C,
+                r#"
+                    #inline_c_rs DIFFERENTIAL
+                    #inline_c_rs DIFFERENTIAL_COMPILERS: "gcc clang"
+                    #include <stdio.h>
+
+                    int main() {
+                    #if defined(__clang__)
+                        printf("clang\n");
+                    #elif defined(__GNUC__)
+                        printf("gcc\n");
+                    #endif
+
+                        return 0;
+                    }
+                "#,
+            )
+            .err()
+            .expect("The builds should behave differently");
+
+            assert!(error
+                .to_string()
+                .starts_with("The builds of `gcc` and `clang` behave differently"));
+        });
+    }
+
+    #[test]
+    fn test_run_with_config() {
+        let mut assert = Config::new()
+            .compiler("gcc")
+            .hermetic(true)
+            .run(Language::C, "int main() { return 0; }")
+            .unwrap();
+
+        assert_eq!(assert.compiler().family(), CompilerFamily::Gcc);
+        assert!(assert.compiler().version().is_some());
+        assert.success();
+
+        let error = Config::new()
+            .hermetic(true)
+            .run(Language::C, "int main() { return 0; }")
+            .err()
+            .expect("The hermetic mode should require a compiler");
+
+        assert!(error.to_string().contains("requires the compiler"));
+    }
+
+    #[test]
+    fn test_run_with_compiler_requirement() {
+        let error = run(
+            Language::C,
+            r#"
+                #inline_c_rs CC: "gcc"
+                #inline_c_rs CC_MIN: "clang>=15 gcc>=999"
+
+                int main() {
+                    return 0;
+                }
+            "#,
+        )
+        .err()
+        .expect("The program should be skipped");
+
+        assert!(error.is::<Skipped>());
+    }
+
+    #[test]
+    fn test_run_with_opt_level() {
+        let program = r#"
+            #inline_c_rs OPT_LEVEL: "2"
+
+            int main() {
+            #if defined(__OPTIMIZE__)
+                return 0;
+            #else
+                return 1;
+            #endif
+            }
+        "#;
+
+        let unoptimized_program = program.replace("#inline_c_rs OPT_LEVEL: \"2\"", "");
+
+        run(Language::C, program).unwrap().success();
+        run(Language::C, &unoptimized_program).unwrap().failure();
+
+        // The directive takes precedence over the configuration.
+        Config::new()
+            .opt_level("0")
+            .run(Language::C, program)
+            .unwrap()
+            .success();
+        Config::new()
+            .opt_level("s")
+            .run(Language::C, &unoptimized_program)
+            .unwrap()
+            .success();
+
+        assert!(Config::new()
+            .opt_level("4")
+            .run(Language::C, "int main() { return 0; }")
+            .is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_with_debug() {
+        let has_debug_info = |assert: &Assert| {
+            std::fs::read(assert.output_path())
+                .unwrap()
+                .windows(b".debug_info".len())
+                .any(|window| window == b".debug_info")
+        };
+        let program = "int main() { return 0; }";
+
+        assert!(!has_debug_info(&run(Language::C, program).unwrap()));
+        assert!(has_debug_info(
+            &run(Language::C, &format!("#inline_c_rs DEBUG\n{}", program)).unwrap()
+        ));
+        assert!(has_debug_info(
+            &Config::new().debug(true).run(Language::C, program).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_run_with_standard() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs STD: "c11"
+
+                int main() {
+                    return __STDC_VERSION__ == 201112L ? 0 : 1;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+
+        Config::new()
+            .standard("c++17")
+            .run(
+                Language::Cxx,
+                "int main() { return __cplusplus == 201703L ? 0 : 1; }",
+            )
+            .unwrap()
+            .success();
+
+        assert!(Config::new()
+            .standard("c++17")
+            .run(Language::C, "int main() { return 0; }")
+            .is_err());
+    }
+
+    #[test]
+    fn test_standard_flag() {
+        assert_eq!(
+            standard_flag(&Language::C, "gnu17", false).unwrap(),
+            "-std=gnu17"
+        );
+        assert_eq!(
+            standard_flag(&Language::Cxx, "c++20", true).unwrap(),
+            "/std:c++20"
+        );
+        assert!(standard_flag(&Language::C, "gnu17", true).is_err());
+        assert!(standard_flag(&Language::Cxx, "c11", false).is_err());
+        assert!(standard_flag(&Language::C, "c11 -O3", false).is_err());
+    }
+
+    #[test]
+    fn test_run_with_includes_and_definitions() {
+        let directory = tempfile::tempdir().unwrap();
+        let include_directory = directory.path().join("with space");
+        std::fs::create_dir(&include_directory).unwrap();
+        std::fs::write(
+            include_directory.join("answer.h"),
+            "#define ANSWER (SIX * SEVEN)\n",
+        )
+        .unwrap();
+
+        let program = format!(
+            r#"
+                #inline_c_rs INCLUDE: ["{}"]
+                #inline_c_rs DEFINE: SIX=6
+                #inline_c_rs DEFINE: GREETING="Hello, World!"
+                #include <stdio.h>
+                #include "answer.h"
+
+                int main() {{
+                    printf("%s %d\n", GREETING, ANSWER);
+
+                    return 0;
+                }}
+            "#,
+            include_directory
+                .display()
+                .to_string()
+                .replace('\\', "\\\\")
+        );
+
+        Config::new()
+            .define("SEVEN", "(1 + 6)")
+            .run(Language::C, &program)
+            .unwrap()
+            .success()
+            .stdout(predicate::eq("Hello, World! 42\n").normalize());
+
+        Config::new()
+            .include(&include_directory)
+            .define("SIX", "6")
+            .define("SEVEN", "7")
+            .define("UNUSED", None)
+            .run(
+                Language::C,
+                "#include \"answer.h\"\nint main() { return ANSWER - 42; }",
+            )
+            .unwrap()
+            .success();
+
+        assert!(run(
+            Language::C,
+            "#inline_c_rs DEFINE: 6=SIX\nint main() { return 0; }"
+        )
+        .is_err());
+        assert!(run(
+            Language::C,
+            "#inline_c_rs INCLUDE: [include]\nint main() { return 0; }"
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_with_quoted_flags() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs CFLAGS: "-DANSWER='(6 * 7)' -DMATH='<math.h>'"
+                #inline_c_rs LDFLAGS: "-L 'a directory with spaces' -lm --gc-sections -fuse-ld=bfd"
+                #include MATH
+
+                int main() {
+                    return (int) sqrt(ANSWER * ANSWER) - 42;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+
+        assert!(run(
+            Language::C,
+            r#"
+                #inline_c_rs CFLAGS: "-DANSWER='42"
+                int main() { return 0; }
+            "#,
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_with_libraries_and_objects() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("answer.c");
+        let object = directory.path().join("answer.o");
+        std::fs::write(&source, "int answer(void) { return 42; }\n").unwrap();
+
+        assert!(Command::new("cc")
+            .arg("-c")
+            .arg(&source)
+            .arg("-o")
+            .arg(&object)
+            .status()
+            .unwrap()
+            .success());
+        assert!(Command::new("ar")
+            .arg("rcs")
+            .arg(directory.path().join("libanswer.a"))
+            .arg(&object)
+            .status()
+            .unwrap()
+            .success());
+
+        let program = r#"
+            #include <math.h>
+
+            int answer(void);
+
+            int main() {
+                volatile double square = answer() * answer();
+
+                return (int) sqrt(square) - 42;
+            }
+        "#;
+
+        run(
+            Language::C,
+            &format!(
+                "#inline_c_rs OBJECTS: [\"{}\"]\n#inline_c_rs LINK: \"m\"\n{}",
+                object.display(),
+                program
+            ),
+        )
+        .unwrap()
+        .success();
+
+        Config::new()
+            .link_search(directory.path())
+            .link("answer")
+            .link("m")
+            .run(Language::C, program)
+            .unwrap()
+            .success();
+    }
+
+    #[test]
+    fn test_run_with_pkg_config() {
+        // When zlib or `pkg-config` are not installed, the test passes, with
+        // a `Skipped:` line on the standard error.
+        crate::skippable(|| {
+            let has_zlib = Command::new("pkg-config")
+                .args(["--exists", "zlib"])
+                .status()
+                .is_ok_and(|status| status.success());
+
+            if !has_zlib {
+                crate::fail(Box::new(Skipped::new(String::from(
+                    "`pkg-config` or zlib is not installed",
+                ))));
+            }
+
+            run(
+                Language::C,
+                r#"
+                    #inline_c_rs PKG_CONFIG: "zlib"
+                    #include <zlib.h>
+
+                    int main() {
+                        return crc32(0, Z_NULL, 0) == 0 ? 0 : 1;
+                    }
+                "#,
+            )
+            .unwrap()
+            .success();
+
+            assert!(Config::new()
+                .pkg_config("inline-c-rs-missing-package")
+                .run(Language::C, "int main() { return 0; }")
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_run_with_env() {
+        let program = r#"
+            #inline_c_rs CFLAGS: "-DUNUSED"
+            #inline_c_rs ENV: GREETING="Hello, World!"
+            #inline_c_rs ENV: ANSWER=42
+            #include <stdio.h>
+            #include <stdlib.h>
+            #include <string.h>
+
+            int main() {
+                const char* path = getenv("PATH");
+                const char* cflags = getenv("CFLAGS");
+
+                printf("%s %s %d %d\n", getenv("GREETING"), getenv("ANSWER"), !(cflags && strcmp(cflags, "-DUNUSED") == 0), path != NULL);
+
+                return 0;
+            }
+        "#;
+
+        run(Language::C, program)
+            .unwrap()
+            .success()
+            .stdout(predicate::eq("Hello, World! 42 1 1\n").normalize());
+        run(Language::C, &format!("#inline_c_rs ENV_CLEAR\n{}", program))
+            .unwrap()
+            .success()
+            .stdout(predicate::eq("Hello, World! 42 1 0\n").normalize());
+        Config::new()
+            .env_remove("PATH")
+            .run(Language::C, program)
+            .unwrap()
+            .env("ANSWER", "43")
+            .success()
+            .stdout(predicate::eq("Hello, World! 43 1 0\n").normalize());
+
+        let error = run(
+            Language::C,
+            "#inline_c_rs GREETING: \"Hello\"\nint main() { return 0; }",
+        )
+        .err()
+        .expect("Unknown settings should be rejected");
+
+        assert!(error.to_string().contains("ENV: GREETING=value"));
+    }
+
+    #[test]
+    fn test_run_with_directive_grammar() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs ENV: GREETING="\"Hello\",\tWorld!"
+                #inline_c_rs DEFINE: EMPTY=""
+                #inline_c_rs CFLAGS: ""
+                #inline_c_rs SHARED: false
+                #inline_c_rs LINK: [
+                    "m",
+                ]
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main() {
+                    printf("%s%s\n", getenv("GREETING"), EMPTY);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout(predicate::eq("\"Hello\",\tWorld!\n").normalize());
+
+        let error = run(Language::C, "#inline_c_rs SHARD\nint main() { return 0; }")
+            .err()
+            .expect("Unknown directives should be rejected");
+
+        assert!(error.to_string().contains("did you mean `SHARED`?"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let environment = [
+            ("INLINE_C_RS_CC", "cc"),
+            ("INLINE_C_RS_CXX", "c++"),
+            ("INLINE_C_RS_OPT_LEVEL", "1"),
+            ("INLINE_C_RS_STD", "c99"),
+            ("INLINE_C_RS_ENV_GREETING", "Hello"),
+            ("INLINE_C_RS_UNKNOWN", "ignored"),
+            ("CC", "ignored"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let (_, directives) = libafl_inline_c_directives::parse(
+            "#inline_c_rs OPT_LEVEL: 3\n#inline_c_rs ENV: GREETING=Bonjour",
+        )
+        .unwrap();
+        let (config, variables, _) = resolve(
+            Config::new().compiler("gcc").opt_level("2"),
+            environment,
+            directives,
+        )
+        .unwrap();
+
+        let mut variables = variables.into_iter().collect::<Vec<_>>();
+        variables.sort();
+
+        assert_eq!(
+            variables,
+            [("CC", "gcc"), ("OPT_LEVEL", "3"), ("STD", "c99")]
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            config.envs,
+            [("GREETING", "Hello"), ("GREETING", "Bonjour")]
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        // The hermetic mode ignores the environment.
+        let (_, directives) = libafl_inline_c_directives::parse("#inline_c_rs HERMETIC").unwrap();
+        let (config, variables, _) = resolve(
+            &Config::new(),
+            vec![
+                (String::from("INLINE_C_RS_CC"), String::from("cc")),
+                (
+                    String::from("INLINE_C_RS_ENV_GREETING"),
+                    String::from("Hello"),
+                ),
+            ],
+            directives,
+        )
+        .unwrap();
+
+        assert!(variables.is_empty());
+        assert!(config.envs.is_empty());
+    }
+
+    #[test]
+    fn test_install() {
+        let program = "#include <stdio.h>\nint main() { printf(\"%d\\n\", ANSWER); return 0; }";
+
+        {
+            let _guard = Config::new().define("ANSWER", "42").install();
+
+            run(Language::C, program)
+                .unwrap()
+                .success()
+                .stdout(predicate::eq("42\n").normalize());
+
+            {
+                let _guard = install_matrix(None, Some("gcc"), Some("2"));
+                let config = Config::current();
+
+                assert_eq!(config.definitions.len(), 1);
+                assert_eq!(config.compiler.as_deref(), Some("gcc"));
+                assert_eq!(config.opt_level.as_deref(), Some("2"));
+            }
+
+            assert_eq!(Config::current().compiler, None);
+        }
+
+        assert!(Config::current().definitions.is_empty());
+        assert!(run(Language::C, program).is_err());
+    }
+
+    #[test]
+    fn test_run_async() {
+        let program = "#include <stdio.h>\nint main() { printf(\"%d\\n\", ANSWER); return 0; }";
+
+        // The installed configuration is read when the compilation starts.
+        let compilation = {
+            let _guard = Config::new().define("ANSWER", "42").install();
+
+            run_async(Language::C, program)
+        };
+        let mut assert = blocking::block_on(compilation).unwrap();
+        let execution = blocking::block_on(assert.execute_async()).unwrap();
+
+        assert!(execution.success());
+        assert_eq!(execution.stdout(), b"42\n");
+
+        let error = blocking::block_on(run_async(Language::C, program))
+            .err()
+            .expect("`ANSWER` is not defined");
+
+        assert!(error.is::<CompilationError>());
+    }
+
     #[test]
     fn test_run_cxx() {
         run(
@@ -0,0 +1,83 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+};
+
+/// The program was not run because its requirements are not met, e.g. the
+/// compiler is older than `CC_MIN`.
+///
+/// `assert_c!` and `assert_cxx!` unwind with this error as payload. The
+/// tests generated by `#[libafl_inline_c::test]` then pass, with the
+/// reason on the standard error, while a plain `#[test]` fails.
+#[derive(Debug)]
+pub struct Skipped(String);
+
+impl Skipped {
+    pub(crate) fn new(reason: String) -> Self {
+        Self(reason)
+    }
+
+    /// Why the program was skipped.
+    pub fn reason(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Skipped: {}", self.0)
+    }
+}
+
+impl Error for Skipped {}
+
+/// Panic with the error returned by `run`, or unwind with it as payload,
+/// without printing a panic message, if it is [`Skipped`].
+#[doc(hidden)]
+pub fn fail(error: Box<dyn Error>) -> ! {
+    match error.downcast::<Skipped>() {
+        Ok(skipped) => {
+            eprintln!("{}", skipped);
+
+            panic::resume_unwind(skipped)
+        }
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// The value returned by a test function when it is skipped.
+#[doc(hidden)]
+pub trait SkippedTest {
+    fn skipped() -> Self;
+}
+
+impl SkippedTest for () {
+    fn skipped() -> Self {}
+}
+
+impl<E> SkippedTest for Result<(), E> {
+    fn skipped() -> Self {
+        Ok(())
+    }
+}
+
+/// Run the body of a test, which passes if it is [`Skipped`]. The test
+/// harness has no way to mark a running test as ignored, so the reason is
+/// written on the standard error, which, unlike `eprintln!`, the harness
+/// does not capture.
+#[doc(hidden)]
+pub fn skippable<T: SkippedTest>(test: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(test)) {
+        Ok(output) => output,
+        Err(payload) => match payload.downcast::<Skipped>() {
+            Ok(skipped) => {
+                let _ = writeln!(io::stderr(), "{}", skipped);
+
+                T::skipped()
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
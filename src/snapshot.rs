@@ -0,0 +1,202 @@
+use crate::execution::Execution;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable rewriting the snapshots when set to `1`.
+const UPDATE_SNAPSHOTS: &str = "INLINE_C_RS_UPDATE_SNAPSHOTS";
+
+lazy_static! {
+    /// The redactions applied before those of the test: addresses, such
+    /// as those printed with `%p`, change from one run to another. They
+    /// are printed with `0x` by glibc, macOS and MinGW, and as 16
+    /// uppercase digits by MSVC on 64-bit targets, which also redacts
+    /// 16-digit numbers. The 8 digits of MSVC on 32-bit targets cannot be
+    /// told apart from numbers, and are kept.
+    static ref DEFAULT_REDACTIONS: Vec<(Regex, String)> = vec![(
+        Regex::new(r"\b0x[0-9a-fA-F]{8,16}\b|\b[0-9A-F]{16}\b").unwrap(),
+        String::from("[ADDRESS]"),
+    )];
+}
+
+/// Render the outcome and the outputs of an execution, with the
+/// redactions applied to the outputs.
+pub(crate) fn render(execution: &Execution, redactions: &[(Regex, String)]) -> String {
+    format!(
+        "--- outcome\n{}\n--- stdout\n{}\n--- stderr\n{}\n",
+        execution.outcome(),
+        redact(execution.stdout(), redactions),
+        redact(execution.stderr(), redactions)
+    )
+}
+
+/// Apply the default redactions, and then `redactions`, to an output.
+fn redact(output: &[u8], redactions: &[(Regex, String)]) -> String {
+    DEFAULT_REDACTIONS.iter().chain(redactions).fold(
+        String::from_utf8_lossy(output).into_owned(),
+        |output, (regex, replacement)| {
+            regex
+                .replace_all(&output, replacement.as_str())
+                .into_owned()
+        },
+    )
+}
+
+/// Return the path of the snapshot `name` of a test, in the `snapshots`
+/// directory next to its source, e.g. `src/snapshots/lib__name.snap` for
+/// a test of `src/lib.rs`.
+pub(crate) fn path(source: &str, name: &str) -> PathBuf {
+    assert!(
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')),
+        "Invalid snapshot name `{}`, expected letters, digits, `_`, `-` or `.`",
+        name
+    );
+
+    // The path of the source is relative to the workspace, which is the
+    // package or one of its ancestors.
+    let source = Path::new(source);
+    let source = env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::current_dir().ok())
+        .and_then(|package| {
+            package
+                .ancestors()
+                .map(|directory| directory.join(source))
+                .find(|source| source.is_file())
+        })
+        .unwrap_or_else(|| source.to_path_buf());
+    let stem = source
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+    source
+        .with_file_name("snapshots")
+        .join(format!("{}__{}.snap", stem, name))
+}
+
+/// Whether the snapshots are rewritten, with
+/// `INLINE_C_RS_UPDATE_SNAPSHOTS=1`.
+pub(crate) fn update() -> bool {
+    env::var_os(UPDATE_SNAPSHOTS).is_some_and(|update| update == "1")
+}
+
+/// Compare the rendered execution with its snapshot, or write the
+/// snapshot if `update` is set.
+pub(crate) fn check(path: &Path, rendered: &str, update: bool) -> Result<(), String> {
+    if update {
+        return path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, rendered))
+            .map_err(|error| {
+                format!(
+                    "Failed to write the snapshot `{}`: {}",
+                    path.display(),
+                    error
+                )
+            });
+    }
+
+    match fs::read_to_string(path) {
+        Ok(snapshot) if snapshot.replace("\r\n", "\n") == rendered => Ok(()),
+        Ok(snapshot) => Err(format!(
+            "The execution does not match the snapshot `{}`; run with `{}=1` to update it.\n\
+             Expected:\n{}\n\
+             Actual:\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS,
+            snapshot,
+            rendered
+        )),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Err(format!(
+            "The snapshot `{}` does not exist; run with `{}=1` to create it.\n\
+             Actual:\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS,
+            rendered
+        )),
+        Err(error) => Err(format!(
+            "Failed to read the snapshot `{}`: {}",
+            path.display(),
+            error
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Language};
+
+    #[test]
+    fn test_render() {
+        let execution = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    int x = 0;
+
+                    printf("%p 0x10 %s\n", (void*) &x, "secret");
+                    fprintf(stderr, "done\n");
+
+                    return 2;
+                }
+            "#,
+        )
+        .unwrap()
+        .execute()
+        .unwrap();
+
+        assert_eq!(
+            render(
+                &execution,
+                &[(Regex::new("secret").unwrap(), String::from("[SECRET]"))]
+            ),
+            "--- outcome\nexited with code 2\n--- stdout\n[ADDRESS] 0x10 [SECRET]\n\n--- stderr\ndone\n\n"
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact(
+                b"0x7ffd5e8c3a1c 0x10 00000087A1B2C3D4 2024010112345678 deadbeefdeadbeef",
+                &[]
+            ),
+            "[ADDRESS] 0x10 [ADDRESS] [ADDRESS] deadbeefdeadbeef"
+        );
+    }
+
+    #[test]
+    fn test_path() {
+        let path = path("src/snapshot.rs", "hello");
+
+        assert!(path.ends_with("src/snapshots/snapshot__hello.snap"));
+        assert!(path.is_absolute());
+    }
+
+    #[test]
+    fn test_check() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("snapshots").join("lib__hello.snap");
+
+        assert!(check(&path, "Hello", false)
+            .unwrap_err()
+            .contains("does not exist"));
+        assert!(check(&path, "Hello", true).is_ok());
+        assert!(check(&path, "Hello", false).is_ok());
+        assert!(check(&path, "World", false)
+            .unwrap_err()
+            .contains("does not match"));
+        assert!(check(&path, "World", true).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "World");
+    }
+}
@@ -0,0 +1,12 @@
+#inline_c_rs ENV: FROM="a file"
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "harness.h"
+
+int main() {
+    printf(GREETING, getenv("FROM"));
+
+    return 0;
+}